@@ -0,0 +1,16 @@
+//! Generate the protobuf messages of the database from the schema of the C++
+//! parser, with the parser of protobuf-codegen so that protoc is not needed.
+//! The schema is a copy of oblique/data.proto, so that the crate can be
+//! packaged on its own; tests/proto_test.rs checks that they are the same.
+
+const SCHEMA: &str = "proto/data.proto";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", SCHEMA);
+    protobuf_codegen::Codegen::new()
+        .pure()
+        .include("proto")
+        .input(SCHEMA)
+        .cargo_out_dir("proto")
+        .run_from_script();
+}
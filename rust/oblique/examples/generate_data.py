@@ -1,6 +1,7 @@
 import random
 import datetime
 import math
+import re
 
 OUTPUT_FILE = "rust/oblique/examples/nuclear_plant.oblique"
 NUM_TASKS = 1500
@@ -45,6 +46,7 @@ generated_tasks = [] # List of task IDs
 generated_milestones = [] # List of milestone IDs
 current_task_id = 5000 # Manual IDs start high to avoid collision with auto-IDs
 next_auto_task_id = 1
+referenced_tasks = set() # Task IDs referred to by other objects
 current_bug_id = 8000
 current_ms_id = 0
 
@@ -232,6 +234,7 @@ def generate_quarter_data(quarter_idx):
             # Variations in text
             style = random.choice(["depends on", "after", "blocked by"])
             links.append(f"{style} t/{prev}")
+            referenced_tasks.add(prev)
             
         # Link to Milestone
         if q_milestones and random.random() < 0.15:
@@ -250,7 +253,8 @@ def generate_quarter_data(quarter_idx):
              full_text = f"{desc} - {link_text}"
 
         if is_auto:
-            lines.append(f"{indent}t/ {full_text}")
+            # Resolved in main() once it is known whether the task is referenced
+            lines.append(f"{indent}t/<auto:{tid}> {full_text}")
         else:
             lines.append(f"{indent}t/{tid} {full_text}")
         
@@ -280,6 +284,7 @@ def generate_quarter_data(quarter_idx):
         task_link = ""
         if q_tasks and random.random() < 0.7:
             task = random.choice(q_tasks)
+            referenced_tasks.add(task)
             if random.random() < 0.5:
                  task_link = f" during t/{task}"
             else:
@@ -292,13 +297,21 @@ def generate_quarter_data(quarter_idx):
 
     return "\n".join(lines)
 
+def resolve_auto_tasks(text):
+    # Generated identifiers start with "_", so the tasks that other objects
+    # refer to by number need that number as an explicit identifier
+    def resolve(match):
+        tid = int(match.group(1))
+        return f"t/{tid}" if tid in referenced_tasks else "t/"
+    return re.sub(r"t/<auto:(\d+)>", resolve, text)
+
 def main():
+    text = generate_header() + generate_static_data()
+    for i in range(len(QUARTERS)):
+        text += generate_quarter_data(i)
+
     with open(OUTPUT_FILE, "w") as f:
-        f.write(generate_header())
-        f.write(generate_static_data())
-        
-        for i in range(len(QUARTERS)):
-            f.write(generate_quarter_data(i))
+        f.write(resolve_auto_tasks(text))
     
     print(f"Generated {OUTPUT_FILE}")
 
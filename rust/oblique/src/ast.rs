@@ -2,6 +2,67 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+
+/// A position in a source file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SourceLocation {
+    /// The file, or `None` for code parsed from a string
+    pub file: Option<PathBuf>,
+
+    /// The line number, starting at 1
+    pub line: usize,
+
+    /// The column number in characters, starting at 1
+    pub column: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A range of bytes in a line or a file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Span {
+    /// The offset of the first byte
+    pub start: usize,
+
+    /// The offset after the last byte
+    pub end: usize,
+}
+
+impl Span {
+    /// Create a new span
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Get the number of bytes in the span
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Check whether the span is empty
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Get the span moved forward by some bytes, e.g. from a line to its file
+    pub fn offset(&self, by: usize) -> Span {
+        Span::new(self.start + by, self.end + by)
+    }
+
+    /// Get the text of the span
+    pub fn slice<'a>(&self, text: &'a str) -> &'a str {
+        &text[self.start..self.end]
+    }
+}
 
 /// The flavor of a type, determining how references to it are handled
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -28,12 +89,12 @@ pub struct Type {
     /// The flavor of the type
     pub flavor: TypeFlavor,
 
-    /// The line number where this type was defined
-    pub lineno: Option<usize>,
+    /// The location where this type was defined
+    pub location: Option<SourceLocation>,
 }
 
 /// An identifier for an object
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct ObjectId {
     /// The type of the object
     pub type_name: String,
@@ -42,14 +103,159 @@ pub struct ObjectId {
     pub ident: Option<String>,
 }
 
-/// A reference to another object
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// The kind of link a reference makes from an object to another
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    /// The object is indented under the other, or imported under it
+    Parent,
+
+    /// The object mentions the other in its contents
+    #[default]
+    Mention,
+
+    /// A macro produced a mention of the other in the contents of the object
+    MacroGenerated,
+}
+
+impl fmt::Display for EdgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EdgeKind::Parent => "parent",
+            EdgeKind::Mention => "mention",
+            EdgeKind::MacroGenerated => "macro_generated",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A reference to another object.
+///
+/// References are the edges of the graph of objects: an object has one
+/// reference per object it links to and kind of link, counting how many times
+/// it occurs. The positions of the mentions are in `Object::occurrences`.
+/// Equality, hashing and ordering ignore the count.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Reference {
     /// The type of the referenced object
     pub type_name: String,
 
     /// The identifier of the referenced object
     pub ident: String,
+
+    /// The kind of link
+    #[serde(default)]
+    pub kind: EdgeKind,
+
+    /// How many times the link occurs
+    #[serde(default = "one")]
+    pub count: usize,
+}
+
+fn one() -> usize {
+    1
+}
+
+impl Reference {
+    /// Create a reference occurring once
+    pub fn new(type_name: impl Into<String>, ident: impl Into<String>, kind: EdgeKind) -> Self {
+        Self { type_name: type_name.into(), ident: ident.into(), kind, count: 1 }
+    }
+
+    /// Check whether two references are to the same object, whatever the
+    /// kind of link
+    pub fn same_target(&self, other: &Reference) -> bool {
+        self.type_name == other.type_name && self.ident == other.ident
+    }
+
+    /// Get the reference with another kind
+    pub fn with_kind(&self, kind: EdgeKind) -> Self {
+        Self { kind, ..self.clone() }
+    }
+
+    fn key(&self) -> (&str, &str, EdgeKind) {
+        (&self.type_name, &self.ident, self.kind)
+    }
+}
+
+impl PartialEq for Reference {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Reference {}
+
+impl std::hash::Hash for Reference {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialOrd for Reference {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Reference {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl From<&Reference> for ObjectId {
+    fn from(reference: &Reference) -> Self {
+        ObjectId {
+            type_name: reference.type_name.clone(),
+            ident: Some(reference.ident.clone()),
+        }
+    }
+}
+
+/// A reference as it occurs in the contents of an object
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReferenceOccurrence {
+    /// The reference
+    pub reference: Reference,
+
+    /// The location of the reference in the source
+    pub location: SourceLocation,
+
+    /// The bytes of the reference in its file, or `None` if it was produced
+    /// by a macro rather than written in the text
+    pub span: Option<Span>,
+}
+
+/// How an object came to be in the database
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectOrigin {
+    /// Defined with an explicit identifier
+    #[default]
+    Explicit,
+
+    /// Defined without an identifier, which was generated
+    AutoId,
+
+    /// Created by the resolution for a reference to a lazy type
+    LazyCreated,
+
+    /// Created by the resolution for a reference to a lazy type that objects
+    /// only link to as their parent, without mentioning it
+    ParentImplied,
+}
+
+impl fmt::Display for ObjectOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ObjectOrigin::Explicit => "explicit",
+            ObjectOrigin::AutoId => "auto_id",
+            ObjectOrigin::LazyCreated => "lazy_created",
+            ObjectOrigin::ParentImplied => "parent_implied",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 /// An object in the Oblique language
@@ -67,6 +273,27 @@ pub struct Object {
     /// References to other objects that have not been resolved
     pub unresolved_refs: HashSet<Reference>,
 
+    /// Where each reference occurs in the contents, in order
+    pub occurrences: Vec<ReferenceOccurrence>,
+
+    /// The location where this object was defined
+    pub location: Option<SourceLocation>,
+
+    /// How the object came to be, e.g. to tell placeholders created for
+    /// references from objects defined with empty contents
+    #[serde(default)]
+    pub origin: ObjectOrigin,
+}
+
+impl Object {
+    /// Check whether the object was created by the resolution rather than
+    /// defined in the source
+    pub fn is_placeholder(&self) -> bool {
+        matches!(self.origin, ObjectOrigin::LazyCreated | ObjectOrigin::ParentImplied)
+    }
+
     /// The line number where this object was defined
-    pub lineno: Option<usize>,
+    pub fn lineno(&self) -> Option<usize> {
+        self.location.as_ref().map(|l| l.line)
+    }
 }
@@ -0,0 +1,228 @@
+//! Language features computed from the text of a document and its database
+
+use lsp_types::{CompletionItem, CompletionItemKind, Position, Range};
+
+use oblique::{lex_line, Database, ObjectId, SourceLocation, Token, TypeFlavor};
+
+/// Get a line of a text, by 0-based index
+pub fn line_text(text: &str, line: usize) -> &str {
+    text.lines().nth(line).unwrap_or("")
+}
+
+/// Convert a byte offset in a line to an LSP character offset, in UTF-16 code
+/// units
+pub fn utf16_offset(line: &str, offset: usize) -> u32 {
+    line[..offset.min(line.len())].chars().map(|c| c.len_utf16() as u32).sum()
+}
+
+/// Convert an LSP character offset in a line to a byte offset
+pub fn byte_offset(line: &str, character: u32) -> usize {
+    let mut units = 0;
+    for (offset, c) in line.char_indices() {
+        if units >= character {
+            return offset;
+        }
+        units += c.len_utf16() as u32;
+    }
+    line.len()
+}
+
+/// Get the LSP position of a source location in a line
+pub fn position(line: &str, location: &SourceLocation) -> Position {
+    let offset = line
+        .char_indices()
+        .nth(location.column.saturating_sub(1))
+        .map_or(line.len(), |(offset, _)| offset);
+    Position::new(location.line.saturating_sub(1) as u32, utf16_offset(line, offset))
+}
+
+/// Get the range of the word starting at a source location in a line, or of
+/// the rest of the line when there is none
+pub fn word_range(line: &str, location: &SourceLocation) -> Range {
+    let start = position(line, location);
+    let start_offset = byte_offset(line, start.character);
+    let rest = &line[start_offset..];
+    let end_offset = match rest.find(char::is_whitespace) {
+        Some(0) | None => line.len(),
+        Some(len) => start_offset + len,
+    };
+    Range::new(start, Position::new(start.line, utf16_offset(line, end_offset)))
+}
+
+/// Find the reference under the cursor in a line, with its range
+pub fn reference_at(line: &str, position: Position) -> Option<(ObjectId, Range)> {
+    let cursor = byte_offset(line, position.character);
+    lex_line(line)
+        .into_iter()
+        .find_map(|t| match t.token {
+            Token::Reference { type_name, ident } => {
+                if (t.span.start..=t.span.end).contains(&cursor) {
+                    let range = Range::new(
+                        Position::new(position.line, utf16_offset(line, t.span.start)),
+                        Position::new(position.line, utf16_offset(line, t.span.end)),
+                    );
+                    Some((ObjectId { type_name, ident: Some(ident) }, range))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+}
+
+/// Get the locations of the references to an object, from the objects
+/// referring to it, in source order after the declaration
+pub fn references(db: &Database, id: &ObjectId, include_declaration: bool) -> Vec<SourceLocation> {
+    let mut locations = Vec::new();
+    for referrer in db.referrers(id).filter_map(|r| db.objects.get(r)) {
+        let occurrences: Vec<_> = referrer
+            .occurrences
+            .iter()
+            .filter(|o| ObjectId::from(&o.reference) == *id)
+            .map(|o| o.location.clone())
+            .collect();
+        // A child refers to its parent without naming it
+        if occurrences.is_empty() {
+            locations.extend(referrer.location.clone());
+        }
+        locations.extend(occurrences);
+    }
+    locations.sort();
+    if include_declaration {
+        if let Some(location) = db.objects.get(id).and_then(|o| o.location.clone()) {
+            locations.insert(0, location);
+        }
+    }
+    locations
+}
+
+/// Complete the word before the cursor in a line: the idents of the objects of
+/// a type after its name and a slash, and type names otherwise
+pub fn completions(db: &Database, line: &str, position: Position) -> Vec<CompletionItem> {
+    let cursor = byte_offset(line, position.character);
+    let word_start = line[..cursor].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let word = &line[word_start..cursor];
+
+    if let Some((type_name, _)) = word.split_once('/') {
+        if db.types.contains_key(type_name) {
+            let mut items: Vec<_> = db
+                .objects
+                .values()
+                .filter(|o| o.id.type_name == type_name)
+                .filter_map(|o| {
+                    Some(CompletionItem {
+                        label: o.id.ident.clone()?,
+                        kind: Some(CompletionItemKind::REFERENCE),
+                        detail: Some(o.contents.clone()).filter(|c| !c.is_empty()),
+                        ..CompletionItem::default()
+                    })
+                })
+                .collect();
+            items.sort_by(|a, b| a.label.cmp(&b.label));
+            return items;
+        }
+    }
+
+    let mut items: Vec<_> = db
+        .types
+        .values()
+        .map(|t| CompletionItem {
+            label: format!("{}/", t.name),
+            kind: Some(CompletionItemKind::CLASS),
+            detail: Some(t.contents.clone()),
+            ..CompletionItem::default()
+        })
+        .collect();
+    items.sort_by(|a, b| a.label.cmp(&b.label));
+    items
+}
+
+/// Describe an object in Markdown, with its rendering
+pub fn hover(db: &Database, id: &ObjectId) -> Option<String> {
+    let object = db.objects.get(id)?;
+    let ident = id.ident.as_deref().unwrap_or("");
+    let mut text = format!(
+        "**{}** `{}/{}`\n",
+        db.render_system.render(&id.type_name, ident),
+        id.type_name,
+        ident
+    );
+    if !object.contents.is_empty() {
+        text.push_str(&format!("\n{}\n", object.contents));
+    }
+    if let Some(typ) = db.types.get(&id.type_name) {
+        let flavor = match typ.flavor {
+            TypeFlavor::Strict => "strict",
+            TypeFlavor::Lazy => "lazy",
+            TypeFlavor::Ignore => "ignore",
+        };
+        text.push_str(&format!("\n*{} ({})*", typ.contents, flavor));
+    }
+    match &object.location {
+        Some(location) => text.push_str(&format!(", defined at {}", location)),
+        None => text.push_str(", created by reference"),
+    }
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn database(text: &str) -> Database {
+        // The file does not exist, only as an unsaved buffer
+        let path = PathBuf::from("/nonexistent/main.oblique");
+        let mut db = Database::new();
+        db.set_collect_diagnostics(true);
+        db.set_unsaved_files(HashMap::from([(path.clone(), text.to_string())]));
+        db.import_file(&path).unwrap();
+        db
+    }
+
+    fn id(type_name: &str, ident: &str) -> ObjectId {
+        ObjectId { type_name: type_name.to_string(), ident: Some(ident.to_string()) }
+    }
+
+    #[test]
+    fn test_positions() {
+        let line = "item/a é𝄞 u/joe";
+        let location = SourceLocation { file: None, line: 3, column: 11 };
+        assert_eq!(position(line, &location), Position::new(2, 11));
+        assert_eq!(byte_offset(line, 11), 14);
+        assert_eq!(word_range(line, &location), Range::new(Position::new(2, 11), Position::new(2, 16)));
+
+        let (id, range) = reference_at(line, Position::new(0, 13)).unwrap();
+        assert_eq!(id, super::ObjectId { type_name: "u".to_string(), ident: Some("joe".to_string()) });
+        assert_eq!(range, Range::new(Position::new(0, 11), Position::new(0, 16)));
+        assert!(reference_at(line, Position::new(0, 7)).is_none());
+    }
+
+    #[test]
+    fn test_references() {
+        let db = database("/type/t Task\nt/a Task A\n  t/b Task B\nitem/x See t/a and t/a\n");
+        let lines: Vec<_> = references(&db, &id("t", "a"), true).iter().map(|l| (l.line, l.column)).collect();
+        assert_eq!(lines, vec![(2, 1), (3, 3), (4, 12), (4, 20)]);
+        assert_eq!(references(&db, &id("t", "b"), false), vec![]);
+    }
+
+    #[test]
+    fn test_completions() {
+        let db = database("/type/t Task\n/lazytype/u User\nt/b Task B\nt/a Task A for u/joe\n");
+        let labels = |items: Vec<CompletionItem>| items.into_iter().map(|i| i.label).collect::<Vec<_>>();
+        assert_eq!(labels(completions(&db, "see t/", Position::new(0, 6))), vec!["a", "b"]);
+        assert_eq!(labels(completions(&db, "see u/j", Position::new(0, 7))), vec!["joe"]);
+        assert_eq!(labels(completions(&db, "see ", Position::new(0, 4))), vec!["item/", "t/", "u/"]);
+    }
+
+    #[test]
+    fn test_hover() {
+        let db = database("/type/t Task\n/lazytype/u User\n/render t <b>\\1</b>\nt/a Task A for u/joe\n");
+        let text = hover(&db, &id("t", "a")).unwrap();
+        assert!(text.starts_with("**<b>a</b>** `t/a`\n\nTask A for u/joe\n"), "{}", text);
+        assert!(text.ends_with("*Task (strict)*, defined at /nonexistent/main.oblique:4:1"), "{}", text);
+        assert!(hover(&db, &id("u", "joe")).unwrap().ends_with("created by reference"));
+        assert!(hover(&db, &id("t", "missing")).is_none());
+    }
+}
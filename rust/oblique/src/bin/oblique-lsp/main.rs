@@ -0,0 +1,309 @@
+//! Language server for Oblique files, over stdio
+
+mod analysis;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, References, Request as _};
+use lsp_types::{
+    CompletionOptions, CompletionResponse, DiagnosticSeverity, GotoDefinitionResponse, Hover, HoverContents,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, Position, PublishDiagnosticsParams,
+    ServerCapabilities, TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+
+use oblique::{Database, Diagnostic, ImportOptions, ObjectId, SourceLocation};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Sync + Send>>;
+
+fn main() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["/".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+                let response = server.handle_request(request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                if server.handle_notification(notification) {
+                    for diagnostics in server.publish_diagnostics() {
+                        let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), diagnostics);
+                        connection.sender.send(Message::Notification(notification))?;
+                    }
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+
+    // The writer thread stops once the connection is dropped
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// A database of the files imported from a root file
+struct Workspace {
+    root: PathBuf,
+    db: Database,
+    /// Errors that stopped the import
+    error: Option<Diagnostic>,
+}
+
+impl Workspace {
+    fn contains(&self, path: &Path) -> bool {
+        self.root == path || self.db.source_files().iter().any(|p| canonical(p) == path)
+    }
+}
+
+/// The state of the server: the open documents and the databases of the
+/// files they import
+#[derive(Default)]
+struct Server {
+    /// The contents of the open documents, by canonical path
+    documents: HashMap<PathBuf, String>,
+
+    /// The databases of the open documents, each of which is in one of them.
+    /// An open document imported by another gets the database of the other,
+    /// to see its vocabulary.
+    workspaces: Vec<Workspace>,
+
+    /// The files diagnostics were last published for
+    published: HashSet<PathBuf>,
+}
+
+impl Server {
+    /// Handle a request, answering null to unknown ones
+    fn handle_request(&mut self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => self.definition(request).map(serde_json::to_value),
+            References::METHOD => self.references(request).map(serde_json::to_value),
+            Completion::METHOD => self.completion(request).map(serde_json::to_value),
+            HoverRequest::METHOD => self.hover(request).map(serde_json::to_value),
+            _ => Ok(Ok(serde_json::Value::Null)),
+        };
+        match result {
+            Ok(Ok(value)) => Response::new_ok(id, value),
+            Ok(Err(err)) => error_response(id, err.to_string()),
+            Err(err) => error_response(id, err),
+        }
+    }
+
+    /// Handle a notification, and return whether the documents changed
+    fn handle_notification(&mut self, notification: Notification) -> bool {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                if let Ok(params) = notification.extract::<lsp_types::DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD) {
+                    if let Some(path) = file_path(&params.text_document.uri) {
+                        self.update(path, Some(params.text_document.text));
+                        return true;
+                    }
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                if let Ok(mut params) = notification.extract::<lsp_types::DidChangeTextDocumentParams>(DidChangeTextDocument::METHOD) {
+                    // Changes are whole documents, as requested
+                    if let (Some(path), Some(change)) = (file_path(&params.text_document.uri), params.content_changes.pop()) {
+                        self.update(path, Some(change.text));
+                        return true;
+                    }
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                if let Ok(params) = notification.extract::<lsp_types::DidCloseTextDocumentParams>(DidCloseTextDocument::METHOD) {
+                    if let Some(path) = file_path(&params.text_document.uri) {
+                        self.update(path, None);
+                        return true;
+                    }
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Set the contents of a document, or close it, and parse it again
+    fn update(&mut self, path: PathBuf, text: Option<String>) {
+        match text {
+            Some(text) => {
+                self.documents.insert(path.clone(), text);
+            }
+            None => {
+                self.documents.remove(&path);
+            }
+        }
+
+        // Drop the databases with no open documents left
+        let documents = &self.documents;
+        self.workspaces.retain(|w| documents.keys().any(|p| w.contains(p)));
+
+        match self.workspaces.iter_mut().find(|w| w.contains(&path)) {
+            Some(workspace) => {
+                workspace.db.set_unsaved_files(self.documents.clone());
+                workspace.error = workspace.db.reload_file(&path).err().map(|e| Diagnostic::from(&e));
+            }
+            None if self.documents.contains_key(&path) => {
+                let mut db = Database::new();
+                db.set_collect_diagnostics(true);
+                db.set_import_options(ImportOptions::from_env());
+                db.set_unsaved_files(self.documents.clone());
+                let error = db.import_file(&path).err().map(|e| Diagnostic::from(&e));
+
+                // The new document may import the roots of other databases
+                let workspace = Workspace { root: path, db, error };
+                self.workspaces.retain(|w| !workspace.contains(&w.root));
+                self.workspaces.push(workspace);
+            }
+            None => {}
+        }
+    }
+
+    /// Get the notifications publishing the diagnostics of all the files, and
+    /// clearing those of the files that have none anymore
+    fn publish_diagnostics(&mut self) -> Vec<PublishDiagnosticsParams> {
+        let mut by_file: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+        for workspace in &self.workspaces {
+            for diagnostic in workspace.db.diagnostics.iter().chain(&workspace.error) {
+                let location = diagnostic.location.clone().unwrap_or_default();
+                let path = location.file.as_deref().map_or_else(|| workspace.root.clone(), canonical);
+                let line = self.line(&path, location.line.saturating_sub(1));
+                let location = SourceLocation { line: location.line.max(1), column: location.column.max(1), ..location };
+                by_file.entry(path).or_default().push(lsp_types::Diagnostic {
+                    range: analysis::word_range(&line, &location),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    source: Some("oblique".to_string()),
+                    message: diagnostic.message.clone(),
+                    ..lsp_types::Diagnostic::default()
+                });
+            }
+        }
+
+        let files: HashSet<PathBuf> = by_file.keys().cloned().collect();
+        for path in self.published.difference(&files) {
+            by_file.entry(path.clone()).or_default();
+        }
+        self.published = files;
+
+        by_file
+            .into_iter()
+            .filter_map(|(path, diagnostics)| {
+                Some(PublishDiagnosticsParams::new(Url::from_file_path(&path).ok()?, diagnostics, None))
+            })
+            .collect()
+    }
+
+    /// Get a line of a file, open or not, by 0-based index
+    fn line(&self, path: &Path, line: usize) -> String {
+        match self.documents.get(path) {
+            Some(text) => analysis::line_text(text, line).to_string(),
+            None => fs::read_to_string(path)
+                .map(|text| analysis::line_text(&text, line).to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Get the LSP location of a source location
+    fn location(&self, location: &SourceLocation) -> Option<Location> {
+        let path = canonical(location.file.as_deref()?);
+        let line = self.line(&path, location.line.saturating_sub(1));
+        Some(Location::new(Url::from_file_path(&path).ok()?, analysis::word_range(&line, location)))
+    }
+
+    /// Find the database of a document and the reference under the cursor
+    fn reference_at(&self, params: &TextDocumentPositionParams) -> Option<(&Database, ObjectId)> {
+        let path = file_path(&params.text_document.uri)?;
+        let workspace = self.workspaces.iter().find(|w| w.contains(&path))?;
+        let line = self.line(&path, params.position.line as usize);
+        let (id, _) = analysis::reference_at(&line, params.position)?;
+        Some((&workspace.db, id))
+    }
+
+    fn definition(&self, request: Request) -> std::result::Result<Option<GotoDefinitionResponse>, String> {
+        let params: lsp_types::GotoDefinitionParams = extract::<GotoDefinition>(request)?;
+        Ok(self.reference_at(&params.text_document_position_params).and_then(|(db, id)| {
+            let location = db.objects.get(&id)?.location.as_ref()?;
+            self.location(location).map(GotoDefinitionResponse::Scalar)
+        }))
+    }
+
+    fn references(&self, request: Request) -> std::result::Result<Option<Vec<Location>>, String> {
+        let params: lsp_types::ReferenceParams = extract::<References>(request)?;
+        Ok(self.reference_at(&params.text_document_position).map(|(db, id)| {
+            analysis::references(db, &id, params.context.include_declaration)
+                .iter()
+                .filter_map(|l| self.location(l))
+                .collect()
+        }))
+    }
+
+    fn completion(&self, request: Request) -> std::result::Result<Option<CompletionResponse>, String> {
+        let params: lsp_types::CompletionParams = extract::<Completion>(request)?;
+        let position = params.text_document_position;
+        let path = match file_path(&position.text_document.uri) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        Ok(self.workspaces.iter().find(|w| w.contains(&path)).map(|w| {
+            let line = self.line(&path, position.position.line as usize);
+            CompletionResponse::Array(analysis::completions(&w.db, &line, position.position))
+        }))
+    }
+
+    fn hover(&self, request: Request) -> std::result::Result<Option<Hover>, String> {
+        let params: lsp_types::HoverParams = extract::<HoverRequest>(request)?;
+        let path = file_path(&params.text_document_position_params.text_document.uri);
+        let position: Position = params.text_document_position_params.position;
+        Ok(self.reference_at(&params.text_document_position_params).and_then(|(db, id)| {
+            let line = self.line(path.as_deref()?, position.line as usize);
+            Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: analysis::hover(db, &id)?,
+                }),
+                range: analysis::reference_at(&line, position).map(|(_, range)| range),
+            })
+        }))
+    }
+}
+
+/// Extract the parameters of a request
+fn extract<R: lsp_types::request::Request>(request: Request) -> std::result::Result<R::Params, String> {
+    request.extract(R::METHOD).map(|(_, params)| params).map_err(|err| format!("{:?}", err))
+}
+
+fn error_response(id: RequestId, message: String) -> Response {
+    Response::new_err(id, lsp_server::ErrorCode::InvalidParams as i32, message)
+}
+
+/// Get the canonical path of a document
+fn file_path(uri: &Url) -> Option<PathBuf> {
+    uri.to_file_path().ok().map(|path| canonical(&path))
+}
+
+/// Get the canonical path of a file, or the path itself if it does not exist
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,141 @@
+//! Lossless concrete syntax tree of Oblique source
+
+use crate::ast::Span;
+use crate::lexer::{lex_line, SpannedToken, Token};
+
+/// A line of source, with the spans of its parts in the whole source
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstLine {
+    /// The line number, starting at 1
+    pub number: usize,
+
+    /// The line, without its terminator
+    pub span: Span,
+
+    /// The whitespace at the start of the line
+    pub indent: Span,
+
+    /// The tokens of the line, ending with an `Eol` token spanning the line
+    /// terminator, which is empty on the last line of a file without one
+    pub tokens: Vec<SpannedToken>,
+}
+
+impl CstLine {
+    /// Get the span of the line terminator, "\n" or "\r\n"
+    pub fn terminator(&self) -> Span {
+        self.tokens.last().map_or(Span::new(self.span.end, self.span.end), |t| t.span)
+    }
+
+    /// Check whether the line has only whitespace
+    pub fn is_blank(&self) -> bool {
+        self.tokens.len() == 1
+    }
+
+    /// Check whether the line has only a comment
+    pub fn is_comment(&self) -> bool {
+        matches!(self.tokens[0].token, Token::Comment(_))
+    }
+
+    /// Get the span from the start of the first token to the end of the last
+    /// one before the comment ending the line, if any, or `None` if there is
+    /// nothing in that range
+    pub fn content_span(&self, from: usize) -> Option<Span> {
+        let tokens: Vec<_> = self.tokens[from.min(self.tokens.len())..]
+            .iter()
+            .take_while(|t| !matches!(t.token, Token::Comment(_) | Token::Eol))
+            .collect();
+        Some(Span::new(tokens.first()?.span.start, tokens.last()?.span.end))
+    }
+}
+
+/// The lines of a source and their tokens, with the spans of all of them, such
+/// that the source can be rebuilt exactly, whitespace and comments included
+#[derive(Debug, Clone)]
+pub struct Cst<'a> {
+    source: &'a str,
+
+    /// The lines of the source
+    pub lines: Vec<CstLine>,
+}
+
+impl<'a> Cst<'a> {
+    /// Split a source into lines and tokenize them
+    pub fn parse(source: &'a str) -> Self {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while start < source.len() {
+            let (end, next) = match source[start..].find('\n') {
+                Some(idx) => (start + idx, start + idx + 1),
+                None => (source.len(), source.len()),
+            };
+            let end = if source[start..end].ends_with('\r') { end - 1 } else { end };
+
+            let text = &source[start..end];
+            let indent = text.len() - text.trim_start().len();
+            let mut tokens: Vec<SpannedToken> = lex_line(text)
+                .into_iter()
+                .map(|t| SpannedToken { token: t.token, span: t.span.offset(start) })
+                .collect();
+            if let Some(eol) = tokens.last_mut() {
+                eol.span = Span::new(end, next);
+            }
+
+            lines.push(CstLine {
+                number: lines.len() + 1,
+                span: Span::new(start, end),
+                indent: Span::new(start, start + indent),
+                tokens,
+            });
+            start = next;
+        }
+        Self { source, lines }
+    }
+
+    /// Get the source
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    /// Get the text of a span of the source
+    pub fn text(&self, span: Span) -> &'a str {
+        span.slice(self.source)
+    }
+
+    /// Get the text of a line, without its terminator
+    pub fn line_text(&self, line: &CstLine) -> &'a str {
+        self.text(line.span)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lossless() {
+        let source = "/type/t  Task\r\n\n  t/a\tTask A  # note\n\t\nitem/x t/a).";
+        let cst = Cst::parse(source);
+        assert_eq!(cst.lines.len(), 5);
+
+        // Gaps between tokens are only whitespace
+        let mut rebuilt = String::new();
+        let mut end = 0;
+        for line in &cst.lines {
+            for token in &line.tokens {
+                let gap = &source[end..token.span.start];
+                assert!(gap.trim().is_empty(), "{:?}", gap);
+                rebuilt.push_str(gap);
+                rebuilt.push_str(cst.text(token.span));
+                end = token.span.end;
+            }
+        }
+        assert_eq!(rebuilt, source);
+
+        assert_eq!(cst.text(cst.lines[0].terminator()), "\r\n");
+        assert_eq!(cst.text(cst.lines[2].indent), "  ");
+        assert_eq!(cst.text(cst.lines[2].content_span(1).unwrap()), "Task A");
+        assert!(cst.lines[3].is_blank());
+        assert_eq!(cst.text(cst.lines[4].tokens[1].span), "t/a");
+        assert!(cst.lines[4].terminator().is_empty());
+    }
+}
@@ -1,12 +1,48 @@
 //! Database for storing Oblique objects and types
 
-use std::collections::{HashMap, HashSet};
-use std::path::Path;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use crate::ast::{Object, ObjectId, Type, TypeFlavor};
-use crate::error::Error;
+use crate::ast::{EdgeKind, Object, ObjectId, ObjectOrigin, Type, TypeFlavor};
+use crate::error::{Diagnostic, Error};
 use crate::macros::RenderSystem;
-use crate::parser;
+use crate::parser::{FileContext, ImportOptions, Parser};
+use crate::proto;
+
+/// Options controlling how references are resolved, like the arguments of the
+/// C++ `Resolve()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolveOptions {
+    /// Report references to undefined objects of strict types as errors,
+    /// instead of just leaving them in `unresolved_refs`
+    pub strict: bool,
+
+    /// Drop references to undeclared types instead of reporting them
+    pub ignore_ref_to_undeclared: bool,
+}
+
+impl Default for ResolveOptions {
+    fn default() -> Self {
+        Self {
+            strict: true,
+            ignore_ref_to_undeclared: false,
+        }
+    }
+}
+
+/// How identifiers are generated for objects defined without one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AutoIdStrategy {
+    /// Number the objects of each type in definition order, so inserting one
+    /// renumbers those after it, like `_1`
+    #[default]
+    Sequential,
+
+    /// Hash the type and contents of the object into six hex digits, like
+    /// `_7f3a2c`, so the identifier only changes with the object itself
+    ContentHash,
+}
 
 /// The database of Oblique objects and types
 #[derive(Debug, Default)]
@@ -20,8 +56,44 @@ pub struct Database {
     /// The render system for the database
     pub render_system: RenderSystem,
 
+    /// Errors collected in diagnostics mode
+    pub diagnostics: Vec<Diagnostic>,
+
     /// Next auto-generated identifier per type
     next_ids: HashMap<String, usize>,
+
+    /// Objects whose identifier was generated
+    auto_ids: HashSet<ObjectId>,
+
+    /// Objects created by the resolution for references to lazy types
+    lazy_objects: HashSet<ObjectId>,
+
+    /// The diagnostics reported by the last resolution, replaced by the next
+    resolve_diagnostics: Vec<Diagnostic>,
+
+    /// The files imported with `import_file`, as given
+    roots: Vec<PathBuf>,
+
+    /// The context of every parsed file, by canonical path
+    files: HashMap<PathBuf, FileContext>,
+
+    /// Contents to parse instead of those on disk, by canonical path
+    unsaved_files: HashMap<PathBuf, String>,
+
+    /// Objects referring to each object, as of the last resolution
+    referrers: HashMap<ObjectId, BTreeSet<ObjectId>>,
+
+    /// Whether errors are collected into `diagnostics` instead of returned
+    collect_diagnostics: bool,
+
+    /// The options used by `resolve_references`
+    resolve_options: ResolveOptions,
+
+    /// The options used by `import_file`
+    import_options: ImportOptions,
+
+    /// How `add_object` generates identifiers
+    auto_id_strategy: AutoIdStrategy,
 }
 
 impl Database {
@@ -31,7 +103,19 @@ impl Database {
             types: HashMap::new(),
             objects: HashMap::new(),
             render_system: RenderSystem::new(),
+            diagnostics: Vec::new(),
             next_ids: HashMap::new(),
+            auto_ids: HashSet::new(),
+            lazy_objects: HashSet::new(),
+            resolve_diagnostics: Vec::new(),
+            roots: Vec::new(),
+            files: HashMap::new(),
+            unsaved_files: HashMap::new(),
+            referrers: HashMap::new(),
+            collect_diagnostics: false,
+            resolve_options: ResolveOptions::default(),
+            import_options: ImportOptions::default(),
+            auto_id_strategy: AutoIdStrategy::default(),
         };
 
         // Add the default item type
@@ -39,12 +123,55 @@ impl Database {
             name: "item".to_string(),
             contents: "Item type".to_string(),
             flavor: TypeFlavor::Lazy,
-            lineno: None,
+            location: None,
         });
 
         db
     }
 
+    /// Collect parse and resolution errors into `diagnostics` and keep going,
+    /// instead of stopping at the first one
+    pub fn set_collect_diagnostics(&mut self, collect: bool) {
+        self.collect_diagnostics = collect;
+    }
+
+    /// Set the options used when resolving references
+    pub fn set_resolve_options(&mut self, options: ResolveOptions) {
+        self.resolve_options = options;
+    }
+
+    /// Set the options used when importing files
+    pub fn set_import_options(&mut self, options: ImportOptions) {
+        self.import_options = options;
+    }
+
+    /// Set contents to parse instead of those of the files on disk, by
+    /// canonical path, e.g. the unsaved buffers of an editor
+    pub fn set_unsaved_files(&mut self, files: HashMap<PathBuf, String>) {
+        self.unsaved_files = files;
+    }
+
+    /// Set how identifiers are generated for objects defined without one
+    pub fn set_auto_id_strategy(&mut self, strategy: AutoIdStrategy) {
+        self.auto_id_strategy = strategy;
+    }
+
+    /// Record an error as a diagnostic, or return it if not collecting
+    fn report(&mut self, err: Error) -> Result<(), Error> {
+        if !self.collect_diagnostics {
+            return Err(err);
+        }
+        self.diagnostics.push(Diagnostic::from(&err));
+        Ok(())
+    }
+
+    /// Record a resolution error, to be dropped by the next resolution
+    fn report_resolution(&mut self, err: Error) -> Result<(), Error> {
+        self.report(err)?;
+        self.resolve_diagnostics.extend(self.diagnostics.last().cloned());
+        Ok(())
+    }
+
     /// Add a type to the database
     pub fn add_type(&mut self, type_def: Type) {
         self.types.insert(type_def.name.clone(), type_def);
@@ -54,10 +181,9 @@ impl Database {
     pub fn add_object(&mut self, mut object: Object) -> Result<(), Error> {
         // Generate an ID if needed
         if object.id.ident.is_none() {
-            let type_name = &object.id.type_name;
-            let next_id = self.next_ids.entry(type_name.clone()).or_insert(1);
-            object.id.ident = Some(format!("{}", next_id));
-            *next_id += 1;
+            object.id.ident = Some(self.generate_id(&object));
+            object.origin = ObjectOrigin::AutoId;
+            self.auto_ids.insert(object.id.clone());
         }
 
         // Check for duplicate definitions
@@ -65,7 +191,7 @@ impl Database {
             return Err(Error::DuplicateDefinition(
                 object.id.type_name.clone(),
                 object.id.ident.clone().unwrap_or_default(),
-                object.lineno.unwrap_or(0),
+                object.location.clone().unwrap_or_default(),
             ));
         }
 
@@ -74,6 +200,42 @@ impl Database {
         Ok(())
     }
 
+    /// Generate an identifier for an object defined without one. Generated
+    /// identifiers start with `_`, so that explicit identifiers defined later
+    /// do not collide with them.
+    fn generate_id(&mut self, object: &Object) -> String {
+        let type_name = &object.id.type_name;
+        match self.auto_id_strategy {
+            AutoIdStrategy::Sequential => {
+                // Skip those already written into the files
+                let next_id = self.next_ids.entry(type_name.clone()).or_insert(1);
+                loop {
+                    let ident = format!("_{}", next_id);
+                    *next_id += 1;
+                    let id = ObjectId { type_name: type_name.clone(), ident: Some(ident.clone()) };
+                    if !self.objects.contains_key(&id) {
+                        break ident;
+                    }
+                }
+            }
+            AutoIdStrategy::ContentHash => {
+                // Objects with the same contents get the hashes of the
+                // contents followed by a counter, in definition order
+                let text = format!("{}/ {}", type_name, object.contents);
+                (0..)
+                    .map(|n| match n {
+                        0 => format!("_{}", short_hash(&text)),
+                        n => format!("_{}", short_hash(&format!("{}\0{}", text, n))),
+                    })
+                    .find(|ident| {
+                        let id = ObjectId { type_name: type_name.clone(), ident: Some(ident.clone()) };
+                        !self.objects.contains_key(&id)
+                    })
+                    .unwrap()
+            }
+        }
+    }
+
     /// Get the type flavor for a type name
     pub fn get_type_flavor(&self, type_name: &str) -> Option<TypeFlavor> {
         self.types.get(type_name).map(|t| t.flavor)
@@ -81,11 +243,19 @@ impl Database {
 
     /// Resolve references in the database
     pub fn resolve_references(&mut self) -> Result<(), Error> {
+        let previous = std::mem::take(&mut self.resolve_diagnostics);
+        self.diagnostics.retain(|d| !previous.contains(d));
+
         let mut objects = std::mem::take(&mut self.objects);
         let mut new_objects: HashMap<ObjectId, Object> = HashMap::new();
 
-        let keys: Vec<ObjectId> = objects.keys().cloned().collect();
+        // Resolve in definition order, so errors are reported deterministically
+        let mut keys: Vec<ObjectId> = objects.keys().cloned().collect();
+        keys.sort_by(|a, b| objects[a].location.cmp(&objects[b].location).then_with(|| a.cmp(b)));
 
+        // The references of each object, set once all of them are resolved,
+        // so that an error leaves the database as it was
+        let mut results = Vec::with_capacity(keys.len());
         for key in &keys {
             let (resolved, unresolved) = {
                 let object = objects.get(key).unwrap();
@@ -93,13 +263,24 @@ impl Database {
                 let mut unresolved = HashSet::new();
 
                 for reference in &object.unresolved_refs {
-                    let type_flavor = self.get_type_flavor(&reference.type_name).ok_or_else(|| {
-                        Error::InvalidType(
-                            reference.type_name.clone(),
-                            reference.ident.clone(),
-                            object.lineno.unwrap_or(0),
-                        )
-                    })?;
+                    let type_flavor = match self.get_type_flavor(&reference.type_name) {
+                        Some(flavor) => flavor,
+                        None if self.resolve_options.ignore_ref_to_undeclared => continue,
+                        None => {
+                            let err = Error::InvalidType(
+                                reference.type_name.clone(),
+                                reference.ident.clone(),
+                                object.location.clone().unwrap_or_default(),
+                            );
+                            if let Err(err) = self.report_resolution(err) {
+                                // Leave the database as it was before resolution
+                                self.objects = objects;
+                                return Err(err);
+                            }
+                            unresolved.insert(reference.clone());
+                            continue;
+                        }
+                    };
 
                     if type_flavor == TypeFlavor::Ignore {
                         continue;
@@ -112,6 +293,13 @@ impl Database {
 
                     let exists = objects.contains_key(&ref_id) || new_objects.contains_key(&ref_id);
 
+                    // An object created for a parent link is mentioned once
+                    // an object refers to it in its contents
+                    let mentioned = reference.kind != EdgeKind::Parent;
+                    if let Some(created) = new_objects.get_mut(&ref_id).filter(|_| mentioned) {
+                        created.origin = ObjectOrigin::LazyCreated;
+                    }
+
                     if exists {
                         resolved.insert(reference.clone());
                     } else if type_flavor == TypeFlavor::Lazy {
@@ -122,53 +310,282 @@ impl Database {
                                 contents: String::new(),
                                 refs: HashSet::new(),
                                 unresolved_refs: HashSet::new(),
-                                lineno: None,
+                                occurrences: Vec::new(),
+                                location: None,
+                                origin: if mentioned { ObjectOrigin::LazyCreated } else { ObjectOrigin::ParentImplied },
                             },
                         );
                         resolved.insert(reference.clone());
                     } else {
                         // Strict and not found
+                        if self.resolve_options.strict {
+                            let err = Error::UndefinedReference(
+                                reference.type_name.clone(),
+                                reference.ident.clone(),
+                                object.location.clone().unwrap_or_default(),
+                            );
+                            if let Err(err) = self.report_resolution(err) {
+                                self.objects = objects;
+                                return Err(err);
+                            }
+                        }
                         unresolved.insert(reference.clone());
                     }
                 }
                 (resolved, unresolved)
             };
 
+            results.push((key, resolved, unresolved));
+        }
+
+        for (key, resolved, unresolved) in results {
             let object = objects.get_mut(key).unwrap();
             object.refs = resolved;
             object.unresolved_refs = unresolved;
         }
 
         // Add the new objects to the database
+        self.lazy_objects.extend(new_objects.keys().cloned());
         objects.extend(new_objects);
         self.objects = objects;
+        self.rebuild_referrers();
         Ok(())
     }
 
+    /// Rebuild the reverse-reference index from the resolved references
+    pub(crate) fn rebuild_referrers(&mut self) {
+        self.referrers.clear();
+        for (id, object) in &self.objects {
+            for reference in &object.refs {
+                self.referrers
+                    .entry(ObjectId::from(reference))
+                    .or_default()
+                    .insert(id.clone());
+            }
+        }
+    }
+
+    /// Get the objects that refer to the given object, in sorted order.
+    ///
+    /// The index is maintained by `resolve_references`, so objects added since
+    /// the last resolution are not included.
+    pub fn referrers(&self, id: &ObjectId) -> impl Iterator<Item = &ObjectId> {
+        self.referrers.get(id).into_iter().flatten()
+    }
+
+    /// Get the objects an object refers to or is referred by, in sorted order
+    pub fn neighbors(&self, id: &ObjectId) -> BTreeSet<ObjectId> {
+        let mut neighbors: BTreeSet<ObjectId> = self.referrers(id).cloned().collect();
+        if let Some(object) = self.objects.get(id) {
+            neighbors.extend(object.refs.iter().map(ObjectId::from));
+        }
+        neighbors
+    }
+
+    /// Get the objects at most some links away from an object, whatever
+    /// their direction, the object included
+    pub fn neighborhood(&self, id: &ObjectId, hops: usize) -> BTreeSet<ObjectId> {
+        let mut found = BTreeSet::from([id.clone()]);
+        let mut frontier = vec![id.clone()];
+        for _ in 0..hops {
+            frontier = frontier
+                .iter()
+                .flat_map(|id| self.neighbors(id))
+                .filter(|neighbor| found.insert(neighbor.clone()))
+                .collect();
+        }
+        found
+    }
+
+    /// Get the object an object is indented or imported under, if any
+    pub fn parent_of(&self, id: &ObjectId) -> Option<ObjectId> {
+        let object = self.objects.get(id)?;
+        object.refs.iter().find(|r| r.kind == EdgeKind::Parent).map(ObjectId::from)
+    }
+
+    /// Get the objects indented or imported under an object, in definition
+    /// order, like `referrers` as of the last resolution
+    pub fn children_of(&self, id: &ObjectId) -> Vec<&ObjectId> {
+        let mut children: Vec<&ObjectId> = self
+            .referrers(id)
+            .filter(|child| self.parent_of(child).as_ref() == Some(id))
+            .collect();
+        children.sort_by(|a, b| self.definition_order(a, b));
+        children
+    }
+
+    /// Get the objects with no parent, in definition order, followed by those
+    /// created by the resolution
+    pub fn top_level_objects(&self) -> Vec<&ObjectId> {
+        let mut top_level: Vec<&ObjectId> = self.objects.keys().filter(|id| self.parent_of(id).is_none()).collect();
+        top_level.sort_by(|a, b| self.definition_order(a, b));
+        top_level
+    }
+
+    /// Compare objects by where they are defined, those with no location last
+    fn definition_order(&self, a: &ObjectId, b: &ObjectId) -> std::cmp::Ordering {
+        let key = |id: &ObjectId| self.objects.get(id).map(|o| (o.location.is_none(), o.location.clone()));
+        key(a).cmp(&key(b)).then_with(|| a.cmp(b))
+    }
+
+    /// Undo the resolution, dropping the objects it created and marking all
+    /// references unresolved again
+    fn unresolve(&mut self) {
+        for id in self.lazy_objects.drain() {
+            self.objects.remove(&id);
+        }
+        for object in self.objects.values_mut() {
+            let refs = std::mem::take(&mut object.refs);
+            object.unresolved_refs.extend(refs);
+        }
+    }
+
     /// Import objects and types from a file
     pub fn import_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
-        let (types, objects, render_system) = parser::parse_file(path.as_ref())?;
+        let mut parser = Parser::new();
+        parser.set_collect_diagnostics(self.collect_diagnostics);
+        parser.set_import_options(self.import_options.clone());
+        parser.set_unsaved_files(self.unsaved_files.clone());
+        self.roots.push(path.as_ref().to_path_buf());
+        parser.parse_file(path.as_ref())?;
+        self.add_parsed(parser)
+    }
+
+    /// Add what a parser produced to the database and resolve it again
+    fn add_parsed(&mut self, mut parser: Parser) -> Result<(), Error> {
+        self.unresolve();
+        self.diagnostics.append(&mut parser.diagnostics);
+        self.files.extend(parser.files);
 
-        for type_def in types {
+        for type_def in parser.types {
             self.add_type(type_def);
         }
 
-        for object in objects {
-            self.add_object(object)?;
+        // Objects with explicit identifiers first, so that generated ones skip
+        // those already written into the files
+        let (explicit, generated): (Vec<_>, Vec<_>) = parser.objects.into_iter().partition(|o| o.id.ident.is_some());
+        for object in explicit.into_iter().chain(generated) {
+            if let Err(err) = self.add_object(object) {
+                self.report(err)?;
+            }
         }
-        
+
         // Merge render system
-        self.render_system.merge(render_system);
+        self.render_system.merge(parser.render_system);
 
         self.resolve_references()?;
 
         Ok(())
     }
+
+    /// Load a database saved in the binary or text protobuf format of
+    /// `data.proto`, without parsing its files again. Files ending in
+    /// `.textproto`, `.txtpb` or `.pbtxt` are read as text.
+    pub fn load_proto<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let bytes = fs::read(path.as_ref())?;
+        if proto::is_text_file(path.as_ref()) {
+            let text = String::from_utf8(bytes).map_err(|_| Error::Proto("file is not UTF-8".to_string()))?;
+            proto::parse_text(&text)
+        } else {
+            proto::decode(&bytes)
+        }
+    }
+
+    /// Get the paths of all the files parsed into the database, as used in
+    /// source locations, in sorted order
+    pub fn source_files(&self) -> Vec<&Path> {
+        let mut files: Vec<&Path> = self.files.values().map(|c| c.path.as_path()).collect();
+        files.sort();
+        files
+    }
+
+    /// Parse a changed file again and replace its objects and types, without
+    /// parsing the other files again. Falls back to `reload` when the change
+    /// affects other files: when the file imports other files or exports
+    /// another vocabulary than before, or when it defines objects with
+    /// generated identifiers, which would be numbered differently.
+    ///
+    /// On a parse error outside diagnostics mode the database is left as it was.
+    pub fn reload_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let canonical = fs::canonicalize(path.as_ref()).unwrap_or_else(|_| path.as_ref().to_path_buf());
+        let mut parser = match Parser::for_file(&self.files, &canonical) {
+            Some(parser) => parser,
+            None => return self.reload(),
+        };
+        let previous = self.files[&canonical].clone();
+        parser.set_collect_diagnostics(self.collect_diagnostics);
+        parser.set_import_options(self.import_options.clone());
+        parser.set_unsaved_files(self.unsaved_files.clone());
+        parser.parse_file(&previous.path)?;
+
+        let defined_here = |location: &Option<crate::ast::SourceLocation>| {
+            location.as_ref().and_then(|l| l.file.as_ref()) == Some(&previous.path)
+        };
+        let context = &parser.files[&canonical];
+        let had_auto_ids = self.auto_ids.iter()
+            .filter_map(|id| self.objects.get(id))
+            .any(|o| defined_here(&o.location));
+        if context.imports != previous.imports
+            || context.exports != previous.exports
+            || had_auto_ids
+            || parser.objects.iter().any(|o| o.id.ident.is_none())
+        {
+            return self.reload();
+        }
+
+        self.objects.retain(|_, o| !defined_here(&o.location));
+        self.types.retain(|_, t| !defined_here(&t.location));
+        self.diagnostics.retain(|d| !defined_here(&d.location));
+        self.add_parsed(parser)
+    }
+
+    /// Parse all the imported files again from scratch
+    pub fn reload(&mut self) -> Result<(), Error> {
+        let roots = std::mem::take(&mut self.roots);
+        let mut db = Database::new();
+        db.set_collect_diagnostics(self.collect_diagnostics);
+        db.set_resolve_options(self.resolve_options);
+        db.set_import_options(self.import_options.clone());
+        db.set_unsaved_files(std::mem::take(&mut self.unsaved_files));
+        db.set_auto_id_strategy(self.auto_id_strategy);
+        *self = db;
+
+        // Keep importing the other files after an error, so that they are all
+        // reloaded again once it is fixed
+        let mut result = Ok(());
+        for root in roots {
+            if let Err(err) = self.import_file(root) {
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+}
+
+/// Hash a text into six hex digits, with 32-bit FNV-1a, which unlike the
+/// hashers of the standard library is the same across versions and platforms
+fn short_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0x811c9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193));
+    format!("{:06x}", hash >> 8)
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::ast::SourceLocation;
+
+    /// Build a resolved database from a source, for the tests of the outputs
+    pub(crate) fn database(source: &str) -> Database {
+        let (types, objects, render_system) = crate::parser::parse_string(source).unwrap();
+        let mut db = Database::new();
+        types.into_iter().for_each(|t| db.add_type(t));
+        for object in objects {
+            db.add_object(object).unwrap();
+        }
+        db.render_system = render_system;
+        db.resolve_references().unwrap();
+        db
+    }
 
     #[test]
     fn test_duplicate_definition() {
@@ -178,7 +595,9 @@ mod tests {
             contents: "test".to_string(),
             refs: HashSet::new(),
             unresolved_refs: HashSet::new(),
-            lineno: Some(1),
+            occurrences: Vec::new(),
+            location: Some(SourceLocation { line: 1, ..SourceLocation::default() }),
+            origin: ObjectOrigin::Explicit,
         };
         db.add_object(obj1.clone()).unwrap();
 
@@ -195,27 +614,29 @@ mod tests {
             name: "s".to_string(),
             contents: "Strict".to_string(),
             flavor: TypeFlavor::Strict,
-            lineno: None,
+            location: None,
         });
 
         // Add object referring to non-existent 's/1'
         let mut unresolved = HashSet::new();
-        unresolved.insert(crate::ast::Reference { type_name: "s".to_string(), ident: "1".to_string() });
+        unresolved.insert(crate::ast::Reference::new("s", "1", crate::ast::EdgeKind::Mention));
         
         db.add_object(Object {
             id: ObjectId { type_name: "i".to_string(), ident: Some("1".to_string()) },
             contents: "ref".to_string(),
             refs: HashSet::new(),
             unresolved_refs: unresolved,
-            lineno: Some(2),
+            occurrences: Vec::new(),
+            location: Some(SourceLocation { line: 2, ..SourceLocation::default() }),
+            origin: ObjectOrigin::Explicit,
         }).unwrap();
 
-        // Resolving should leave it unresolved (or fail? The current implementation splits them into resolved/unresolved but doesn't error unless type is missing)
-        // Wait, looking at resolve_references:
-        // Strict -> checks if key exists. If yes -> resolved. If no -> unresolved.
-        // It does NOT return an error for missing strict references, it just keeps them in unresolved_refs.
-        // Errors only happen if type doesn't exist.
-        
+        // By default, resolving reports the missing reference
+        let result = db.resolve_references();
+        assert!(matches!(result, Err(Error::UndefinedReference(ref t, ref i, ref l)) if t == "s" && i == "1" && l.line == 2));
+
+        // Leniently, it is just left in unresolved_refs
+        db.set_resolve_options(ResolveOptions { strict: false, ..ResolveOptions::default() });
         db.resolve_references().unwrap();
         
         let obj = db.objects.get(&ObjectId { type_name: "i".to_string(), ident: Some("1".to_string()) }).unwrap();
@@ -223,23 +644,152 @@ mod tests {
         assert!(obj.refs.is_empty());
     }
 
+    #[test]
+    fn test_failed_resolution_leaves_database() {
+        let (types, objects, _) = crate::parser::parse_string("/lazytype/u User\n/type/s Strict\ni/1 For u/joe\ni/2 For s/missing\n").unwrap();
+        let mut db = Database::new();
+        types.into_iter().for_each(|t| db.add_type(t));
+        for object in objects {
+            db.add_object(object).unwrap();
+        }
+
+        // The error on the second object leaves the first unresolved, and
+        // creates no object for its reference
+        assert!(matches!(db.resolve_references(), Err(Error::UndefinedReference(..))));
+        let first = &db.objects[&ObjectId { type_name: "i".to_string(), ident: Some("1".to_string()) }];
+        assert!(first.refs.is_empty());
+        assert_eq!(first.unresolved_refs.len(), 1);
+        let joe = ObjectId { type_name: "u".to_string(), ident: Some("joe".to_string()) };
+        assert!(!db.objects.contains_key(&joe));
+        assert_eq!(db.referrers(&joe).count(), 0);
+    }
+
     #[test]
     fn test_invalid_type_reference() {
         let mut db = Database::new();
         
         // Add object referring to unknown type 'x'
         let mut unresolved = HashSet::new();
-        unresolved.insert(crate::ast::Reference { type_name: "x".to_string(), ident: "1".to_string() });
+        unresolved.insert(crate::ast::Reference::new("x", "1", crate::ast::EdgeKind::Mention));
         
         db.add_object(Object {
             id: ObjectId { type_name: "i".to_string(), ident: Some("1".to_string()) },
             contents: "ref".to_string(),
             refs: HashSet::new(),
             unresolved_refs: unresolved,
-            lineno: Some(2),
+            occurrences: Vec::new(),
+            location: Some(SourceLocation { line: 2, ..SourceLocation::default() }),
+            origin: ObjectOrigin::Explicit,
         }).unwrap();
 
         let result = db.resolve_references();
         assert!(matches!(result, Err(Error::InvalidType(_, _, _))));
+
+        db.set_resolve_options(ResolveOptions { ignore_ref_to_undeclared: true, ..ResolveOptions::default() });
+        db.resolve_references().unwrap();
+        let obj = db.objects.values().next().unwrap();
+        assert!(obj.refs.is_empty());
+        assert!(obj.unresolved_refs.is_empty());
+    }
+
+    #[test]
+    fn test_collect_diagnostics() {
+        let mut db = Database::new();
+        db.set_collect_diagnostics(true);
+
+        let mut unresolved = HashSet::new();
+        unresolved.insert(crate::ast::Reference::new("x", "1", crate::ast::EdgeKind::Mention));
+        unresolved.insert(crate::ast::Reference::new("y", "1", crate::ast::EdgeKind::Mention));
+        let obj = Object {
+            id: ObjectId { type_name: "item".to_string(), ident: Some("1".to_string()) },
+            contents: "ref".to_string(),
+            refs: HashSet::new(),
+            unresolved_refs: unresolved,
+            occurrences: Vec::new(),
+            location: Some(SourceLocation { line: 3, ..SourceLocation::default() }),
+            origin: ObjectOrigin::Explicit,
+        };
+        db.add_object(obj.clone()).unwrap();
+        if let Err(err) = db.add_object(obj) {
+            db.report(err).unwrap();
+        }
+
+        // Both unknown types are reported and the object is kept
+        db.resolve_references().unwrap();
+        assert_eq!(db.diagnostics.len(), 3);
+        assert!(db.diagnostics.iter().all(|d| d.location.as_ref().unwrap().line == 3));
+        assert_eq!(db.objects.len(), 1);
+    }
+
+    #[test]
+    fn test_referrers_and_neighbors() {
+        let mut db = Database::new();
+        db.add_type(Type {
+            name: "u".to_string(),
+            contents: "User".to_string(),
+            flavor: TypeFlavor::Lazy,
+            location: None,
+        });
+
+        for (ident, target) in [("1", "alice"), ("2", "alice"), ("3", "bob")] {
+            let mut unresolved = HashSet::new();
+            unresolved.insert(crate::ast::Reference::new("u", target, crate::ast::EdgeKind::Mention));
+            db.add_object(Object {
+                id: ObjectId { type_name: "item".to_string(), ident: Some(ident.to_string()) },
+                contents: String::new(),
+                refs: HashSet::new(),
+                unresolved_refs: unresolved,
+                occurrences: Vec::new(),
+                location: None,
+                origin: ObjectOrigin::Explicit,
+            }).unwrap();
+        }
+        db.resolve_references().unwrap();
+
+        let alice = ObjectId { type_name: "u".to_string(), ident: Some("alice".to_string()) };
+        let referrers: Vec<_> = db.referrers(&alice).map(|id| id.ident.clone().unwrap()).collect();
+        assert_eq!(referrers, vec!["1", "2"]);
+
+        let item3 = ObjectId { type_name: "item".to_string(), ident: Some("3".to_string()) };
+        let bob = ObjectId { type_name: "u".to_string(), ident: Some("bob".to_string()) };
+        assert_eq!(db.neighbors(&item3).into_iter().collect::<Vec<_>>(), vec![bob.clone()]);
+        assert_eq!(db.neighbors(&bob).into_iter().collect::<Vec<_>>(), vec![item3.clone()]);
+        assert_eq!(db.referrers(&item3).count(), 0);
+
+        let item1 = ObjectId { type_name: "item".to_string(), ident: Some("1".to_string()) };
+        let item2 = ObjectId { type_name: "item".to_string(), ident: Some("2".to_string()) };
+        assert_eq!(db.neighborhood(&item1, 0), BTreeSet::from([item1.clone()]));
+        assert_eq!(db.neighborhood(&item1, 1), BTreeSet::from([item1.clone(), alice.clone()]));
+        assert_eq!(db.neighborhood(&item1, 5), BTreeSet::from([item1, item2, alice]));
+    }
+
+    #[test]
+    fn test_content_hash_ids() {
+        let object = |contents: &str| Object {
+            id: ObjectId { type_name: "t".to_string(), ident: None },
+            contents: contents.to_string(),
+            refs: HashSet::new(),
+            unresolved_refs: HashSet::new(),
+            occurrences: Vec::new(),
+            location: None,
+            origin: ObjectOrigin::Explicit,
+        };
+        let idents = |contents: &[&str]| {
+            let mut db = Database::new();
+            db.set_auto_id_strategy(AutoIdStrategy::ContentHash);
+            for c in contents {
+                db.add_object(object(c)).unwrap();
+            }
+            let mut idents: Vec<_> = db.objects.into_values().map(|o| (o.contents, o.id.ident.unwrap())).collect();
+            idents.sort();
+            idents
+        };
+
+        // Inserting an object leaves the identifiers of the others as they were
+        let before = idents(&["Fix it", "Ship it", "Fix it"]);
+        let after = idents(&["New", "Fix it", "Ship it", "Fix it"]);
+        assert!(before.iter().all(|i| after.contains(i)));
+        assert!(before.iter().all(|(_, ident)| ident.len() == 7 && ident.starts_with('_')));
+        assert_ne!(before[0].1, before[1].1);
     }
 }
@@ -1,51 +1,161 @@
+//! Graphs of the objects in the Graphviz DOT language
+//!
+//! Nodes are named by their reference in quoted strings and labelled with
+//! HTML-like labels, so that any identifier, contents or rendered template is
+//! drawn as it is.
+
+use crate::ast::{EdgeKind, Object, ObjectId, Reference};
 use crate::database::Database;
+use crate::export::{truncate, type_styles, walk, Cluster, Exporter, GraphOptions};
+use crate::html::escape;
+use crate::macros::StyleAttributes;
+use std::collections::BTreeMap;
 use std::io::{self, Write};
 
-fn sanitize_id(type_name: &str, ident: &str) -> String {
-    format!("{}_{}", type_name, ident).replace(|c: char| !c.is_alphanumeric(), "_")
+pub use crate::export::Clustering;
+
+/// Options controlling the graph
+pub type DotOptions = GraphOptions;
+
+/// Quote a string for DOT, where a backslash escapes the next character
+fn quote(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\r', "");
+    format!("\"{}\"", escaped)
 }
 
-pub fn generate_dot<W: Write>(db: &Database, mut writer: W) -> io::Result<()> {
-    writeln!(writer, "digraph Oblique {{ ")?;
-    writeln!(writer, "  rankdir=LR;")?;
-    writeln!(writer, "  node [shape=box, style=filled, fillcolor=white, fontname=\"Arial\"];")?;
-    writeln!(writer, "  edge [color=\"#888888\"];")?;
-
-    for (id, obj) in &db.objects {
-        let node_id = sanitize_id(&id.type_name, id.ident.as_deref().unwrap_or(""));
-        let rendered_label = db.render_system.render(&id.type_name, id.ident.as_deref().unwrap_or(""));
-        
-        // Truncate content for label
-        let content_preview = if obj.contents.len() > 40 {
-            format!("{}\
-...", &obj.contents[..40])
-        } else {
-            obj.contents.clone()
-        };
-        
-        // Escape quotes
-        let label = format!("{}\
-{}", rendered_label, content_preview).replace("\"", "\\\"");
-        
-        // Style based on type
-        let (shape, color) = match id.type_name.as_str() {
-            "q" => ("folder", "#E6F3FF"),      // Quarter (Blue-ish)
-            "p" => ("component", "#EEEEEE"),   // Project (Grey)
-            "u" => ("ellipse", "#FFF9C4"),     // User (Yellow)
-            "t" => ("note", "#FFFFFF"),        // Task (White)
-            "b" => ("diamond", "#FFCDD2"),     // Bug (Red)
-            "m" => ("hexagon", "#C8E6C9"),     // Milestone (Green)
-            "item" => ("plaintext", "white"),
-            _ => ("box", "white"),
+/// Get the name of the node of an object
+fn node_id(id: &ObjectId) -> String {
+    quote(&format!("{}/{}", id.type_name, id.ident.as_deref().unwrap_or("")))
+}
+
+/// Get an HTML-like label showing some text as it is
+fn html_label(text: &str) -> String {
+    format!("<{}>", escape(text).replace('\n', "<BR/>"))
+}
+
+/// The writer of a graph in DOT
+struct DotExporter<'a, W: Write> {
+    db: &'a Database,
+    options: &'a DotOptions,
+    styles: BTreeMap<String, StyleAttributes>,
+    writer: W,
+    indent: &'static str,
+}
+
+impl<W: Write> Exporter for DotExporter<'_, W> {
+    fn begin(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "digraph Oblique {{")?;
+        writeln!(self.writer, "  rankdir={};", quote(&self.options.rankdir))?;
+        writeln!(self.writer, "  node [shape=box, style=filled, fillcolor=white, fontname=\"Arial\"];")?;
+        writeln!(self.writer, "  edge [color=\"#888888\"];")
+    }
+
+    fn begin_cluster(&mut self, cluster: &Cluster) -> io::Result<()> {
+        writeln!(self.writer, "  subgraph {} {{", quote(&format!("cluster_{}", cluster.name)))?;
+        writeln!(self.writer, "    label={};", html_label(&cluster.label))?;
+        self.indent = "    ";
+        Ok(())
+    }
+
+    fn end_cluster(&mut self) -> io::Result<()> {
+        self.indent = "  ";
+        writeln!(self.writer, "  }}")
+    }
+
+    fn node(&mut self, obj: &Object) -> io::Result<()> {
+        let id = &obj.id;
+        let rendered = self.db.render_system.render(&id.type_name, id.ident.as_deref().unwrap_or(""));
+
+        // The rendering in bold over the beginning of the contents
+        let mut label = format!("<<B>{}</B>", escape(&rendered));
+        if !obj.contents.is_empty() {
+            let preview = truncate(&obj.contents, self.options.max_label_len);
+            label.push_str(&format!("<BR/>{}", escape(&preview).replace('\n', "<BR/>")));
+        }
+        label.push('>');
+
+        let mut attributes = vec![format!("label={}", label)];
+        let mut node_style = "filled".to_string();
+        for (name, value) in &self.styles[&id.type_name] {
+            if name == "style" {
+                node_style = value.clone();
+            } else {
+                attributes.push(format!("{}={}", name, quote(value)));
+            }
+        }
+
+        // Objects created for references are only placeholders
+        if obj.is_placeholder() {
+            node_style.push_str(",dashed");
+        }
+        attributes.push(format!("style={}", quote(&node_style)));
+
+        writeln!(self.writer, "{}{} [{}];", self.indent, node_id(id), attributes.join(", "))
+    }
+
+    fn edge(&mut self, from: &Object, reference: &Reference) -> io::Result<()> {
+        let source = node_id(&from.id);
+        let target = node_id(&ObjectId::from(reference));
+        let mut attributes = match reference.kind {
+            EdgeKind::Parent => vec!["style=bold".to_string(), "arrowhead=diamond".to_string()],
+            EdgeKind::Mention => vec![],
+            EdgeKind::MacroGenerated => vec!["style=dashed".to_string()],
         };
+        if reference.count > 1 {
+            attributes.push(format!("label=\"{}\"", reference.count));
+        }
+        if attributes.is_empty() {
+            writeln!(self.writer, "  {} -> {};", source, target)
+        } else {
+            writeln!(self.writer, "  {} -> {} [{}];", source, target, attributes.join(", "))
+        }
+    }
 
-        writeln!(writer, "  {} [label=\" { } \", shape={}, fillcolor=\" { } \"];", node_id, label, shape, color)?;
+    fn end(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "}}")
+    }
+}
+
+/// Write the graph of the objects in DOT
+pub fn generate_dot<W: Write>(db: &Database, options: &DotOptions, writer: W) -> io::Result<()> {
+    let styles = type_styles(db, options);
+    walk(db, options, &mut DotExporter { db, options, styles, writer, indent: "  " })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::database;
 
-        for reference in &obj.refs {
-             let target_id = sanitize_id(&reference.type_name, &reference.ident);
-             writeln!(writer, "  {} -> {};", node_id, target_id)?;
+    #[test]
+    fn test_clusters_and_filter() {
+        let db = database("/type/q Quarter\n/type/t Task\nq/one\n  t/a A\n    t/b B\nt/c C for t/a\nt/d D\n");
+        let dot = |options: &DotOptions| {
+            let mut output = Vec::new();
+            generate_dot(&db, options, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        // Objects are clustered with their top-level ancestor, alone otherwise
+        let clustered = dot(&DotOptions { clustering: Clustering::Parent, ..DotOptions::default() });
+        let cluster = &clustered[clustered.find("subgraph \"cluster_q/one\" {").unwrap()..];
+        let cluster = &cluster[..cluster.find("  }").unwrap()];
+        for node in ["\"q/one\" [", "\"t/a\" [", "\"t/b\" ["] {
+            assert!(cluster.contains(node), "{}", clustered);
         }
+        assert!(!cluster.contains("\"t/c\" ["), "{}", clustered);
+        assert_eq!(clustered.matches("subgraph").count(), 1, "{}", clustered);
+
+        // Filtering keeps the objects and the links between them
+        let objects = db.neighborhood(&ObjectId { type_name: "t".to_string(), ident: Some("c".to_string()) }, 1);
+        let filtered = dot(&DotOptions { objects: Some(objects), ..DotOptions::default() });
+        assert!(filtered.contains("\"t/c\" -> \"t/a\";"), "{}", filtered);
+        assert!(!filtered.contains("\"t/b\" ["), "{}", filtered);
+        assert!(!filtered.contains("\"t/a\" -> \"q/one\""), "{}", filtered);
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("a \"b\" c:\\d\ne"), "\"a \\\"b\\\" c:\\\\d\\ne\"");
     }
-    writeln!(writer, "}}")?;
-    Ok(())
 }
@@ -1,29 +1,105 @@
 //! Error types for the Oblique parser
 
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::path::PathBuf;
 use thiserror::Error;
 
+use crate::ast::SourceLocation;
+
 /// Errors that can occur during parsing and processing of Oblique files
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
-    #[error("Parse error at line {line}: {message}")]
-    Parse { line: usize, message: String },
+    #[error("Parse error at {location}: {message}")]
+    Parse { location: SourceLocation, message: String },
+
+    #[error("Definition for undeclared type '{0}' at {1}")]
+    UndeclaredType(String, SourceLocation),
+
+    #[error("Invalid type '{0}' in reference '{0}/{1}' at {2}")]
+    InvalidType(String, String, SourceLocation),
+
+    #[error("Duplicate definition for '{0}/{1}' at {2}")]
+    DuplicateDefinition(String, String, SourceLocation),
+
+    #[error("Reference to undefined object '{0}/{1}' of strict type at {2}")]
+    UndefinedReference(String, String, SourceLocation),
+
+    #[error("Failed to import file {0} at {1}: {2}")]
+    Import(PathBuf, SourceLocation, Box<Error>),
+
+    #[error("Import cycle at {1}: {}", display_chain(.0))]
+    ImportCycle(Vec<PathBuf>, SourceLocation),
+
+    #[error("Type '{0}' is not in scope at {1}; import the file declaring it with /import --vocab")]
+    TypeNotInScope(String, SourceLocation),
+
+    #[error("Invalid macro pattern '{0}' at {1}")]
+    InvalidMacroPattern(String, SourceLocation),
 
-    #[error("Definition for undeclared type '{0}' at line {1}")]
-    UndeclaredType(String, usize),
+    #[error("Invalid query: {0}")]
+    Query(String),
 
-    #[error("Invalid type '{0}' in reference '{0}/{1}' at line {2}")]
-    InvalidType(String, String, usize),
+    #[error("Cannot rename: {0}")]
+    Rename(String),
 
-    #[error("Duplicate definition for '{0}/{1}' at line {2}")]
-    DuplicateDefinition(String, String, usize),
+    #[error("Invalid protobuf: {0}")]
+    Proto(String),
+}
+
+/// Format a chain of imported files as "a -> b -> a"
+fn display_chain(chain: &[PathBuf]) -> String {
+    chain
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+impl Error {
+    /// The source location the error refers to, if any. I/O, query, rename and
+    /// protobuf errors are not tied to a position in the source.
+    pub fn location(&self) -> Option<&SourceLocation> {
+        match self {
+            Error::Parse { location, .. } => Some(location),
+            Error::UndeclaredType(_, location)
+            | Error::InvalidType(_, _, location)
+            | Error::DuplicateDefinition(_, _, location)
+            | Error::UndefinedReference(_, _, location)
+            | Error::Import(_, location, _)
+            | Error::ImportCycle(_, location)
+            | Error::TypeNotInScope(_, location)
+            | Error::InvalidMacroPattern(_, location) => Some(location),
+            Error::Io(_) | Error::Query(_) | Error::Rename(_) | Error::Proto(_) => None,
+        }
+    }
+}
 
-    #[error("Failed to import file {0}: {1}")]
-    Import(PathBuf, Box<Error>),
+/// An error collected while parsing or resolving in diagnostics mode, like the
+/// `Error` message of `data.proto`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// Where the error occurred, if known
+    pub location: Option<SourceLocation>,
+
+    /// The error message
+    pub message: String,
+}
+
+impl From<&Error> for Diagnostic {
+    fn from(err: &Error) -> Self {
+        Diagnostic {
+            location: err.location().cloned(),
+            message: err.to_string(),
+        }
+    }
+}
 
-    #[error("Invalid macro pattern: {0}")]
-    InvalidMacroPattern(String),
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
 }
@@ -0,0 +1,96 @@
+//! Cypher statements loading the objects into Neo4j or Memgraph
+//!
+//! Every object is an `Object` node also labelled with its type, and every
+//! link a relationship named after its kind, e.g. `MENTION`.
+
+use super::{walk, Exporter, GraphOptions};
+use crate::ast::{EdgeKind, Object, ObjectId, Reference};
+use crate::database::Database;
+use std::io::{self, Write};
+
+/// Quote a string for Cypher
+fn quote(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('\'', "\\'").replace('\n', "\\n").replace('\r', "\\r");
+    format!("'{}'", escaped)
+}
+
+/// Get a pattern matching the node of an object
+fn node_pattern(id: &ObjectId) -> String {
+    format!("(:Object {{type: {}, ident: {}}})", quote(&id.type_name), quote(id.ident.as_deref().unwrap_or("")))
+}
+
+/// The writer of a graph as Cypher statements
+struct CypherExporter<'a, W: Write> {
+    db: &'a Database,
+    writer: W,
+}
+
+impl<W: Write> Exporter for CypherExporter<'_, W> {
+    fn begin(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "CREATE INDEX IF NOT EXISTS FOR (o:Object) ON (o.type, o.ident);")
+    }
+
+    fn node(&mut self, obj: &Object) -> io::Result<()> {
+        let id = &obj.id;
+        let ident = id.ident.as_deref().unwrap_or("");
+        let label = self.db.render_system.render(&id.type_name, ident);
+        writeln!(
+            self.writer,
+            "CREATE (:Object:`{}` {{type: {}, ident: {}, label: {}, contents: {}, origin: {}}});",
+            id.type_name.replace('`', "``"),
+            quote(&id.type_name),
+            quote(ident),
+            quote(&label),
+            quote(&obj.contents),
+            quote(&obj.origin.to_string())
+        )
+    }
+
+    fn edge(&mut self, from: &Object, reference: &Reference) -> io::Result<()> {
+        let relationship = match reference.kind {
+            EdgeKind::Parent => "PARENT",
+            EdgeKind::Mention => "MENTION",
+            EdgeKind::MacroGenerated => "MACRO_GENERATED",
+        };
+        // Name the nodes of the patterns to link them
+        let source = node_pattern(&from.id).replacen("(:", "(a:", 1);
+        let target = node_pattern(&ObjectId::from(reference)).replacen("(:", "(b:", 1);
+        writeln!(
+            self.writer,
+            "MATCH {}, {} CREATE (a)-[:{} {{count: {}}}]->(b);",
+            source, target, relationship, reference.count
+        )
+    }
+
+    fn end(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Write Cypher statements creating the graph of the objects
+pub fn generate_cypher<W: Write>(db: &Database, options: &GraphOptions, writer: W) -> io::Result<()> {
+    walk(db, options, &mut CypherExporter { db, writer })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::database;
+
+    #[test]
+    fn test_cypher() {
+        let db = database("/type/t Task\nt/a Don't copy C:\\temp\n  t/b See t/a\n");
+        let mut output = Vec::new();
+        generate_cypher(&db, &GraphOptions::default(), &mut output).unwrap();
+        let cypher = String::from_utf8(output).unwrap();
+
+        assert!(cypher.starts_with("CREATE INDEX IF NOT EXISTS FOR (o:Object) ON (o.type, o.ident);\n"), "{}", cypher);
+        assert!(cypher.contains(
+            "CREATE (:Object:`t` {type: 't', ident: 'a', label: 't/a', contents: 'Don\\'t copy C:\\\\temp', origin: 'explicit'});"
+        ), "{}", cypher);
+        assert!(cypher.contains(
+            "MATCH (a:Object {type: 't', ident: 'b'}), (b:Object {type: 't', ident: 'a'}) CREATE (a)-[:MENTION {count: 1}]->(b);"
+        ), "{}", cypher);
+        assert!(cypher.contains("CREATE (a)-[:PARENT {count: 1}]->(b);"), "{}", cypher);
+    }
+}
@@ -0,0 +1,123 @@
+//! GraphML, read by yEd, Gephi, NetworkX and most graph libraries
+//!
+//! Nodes are named by their reference and carry the full contents, since the
+//! tools reading GraphML show and filter their data rather than draw labels.
+
+use super::{walk, Cluster, Exporter, GraphOptions};
+use crate::ast::{Object, ObjectId, Reference};
+use crate::database::Database;
+use crate::html::escape;
+use std::io::{self, Write};
+
+/// The data of nodes and edges, as (id, element, name)
+const KEYS: &[(&str, &str, &str)] = &[
+    ("type", "node", "type"),
+    ("ident", "node", "ident"),
+    ("label", "node", "label"),
+    ("contents", "node", "contents"),
+    ("origin", "node", "origin"),
+    ("cluster", "node", "cluster"),
+    ("kind", "edge", "kind"),
+    ("count", "edge", "count"),
+];
+
+/// Get the name of the node of an object
+fn node_id(id: &ObjectId) -> String {
+    escape(&format!("{}/{}", id.type_name, id.ident.as_deref().unwrap_or("")))
+}
+
+/// The writer of a graph in GraphML
+struct GraphmlExporter<'a, W: Write> {
+    db: &'a Database,
+    writer: W,
+    cluster: Option<String>,
+}
+
+impl<W: Write> GraphmlExporter<'_, W> {
+    fn data(&mut self, key: &str, value: &str) -> io::Result<()> {
+        writeln!(self.writer, "      <data key=\"{}\">{}</data>", key, escape(value))
+    }
+}
+
+impl<W: Write> Exporter for GraphmlExporter<'_, W> {
+    fn begin(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(self.writer, "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">")?;
+        for (id, element, name) in KEYS {
+            let kind = if *id == "count" { "int" } else { "string" };
+            writeln!(self.writer, "  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"/>", id, element, name, kind)?;
+        }
+        writeln!(self.writer, "  <graph id=\"Oblique\" edgedefault=\"directed\">")
+    }
+
+    fn begin_cluster(&mut self, cluster: &Cluster) -> io::Result<()> {
+        self.cluster = Some(cluster.name.clone());
+        Ok(())
+    }
+
+    fn end_cluster(&mut self) -> io::Result<()> {
+        self.cluster = None;
+        Ok(())
+    }
+
+    fn node(&mut self, obj: &Object) -> io::Result<()> {
+        let id = &obj.id;
+        let ident = id.ident.as_deref().unwrap_or("");
+        writeln!(self.writer, "    <node id=\"{}\">", node_id(id))?;
+        self.data("type", &id.type_name)?;
+        self.data("ident", ident)?;
+        self.data("label", &self.db.render_system.render(&id.type_name, ident))?;
+        self.data("contents", &obj.contents)?;
+        self.data("origin", &obj.origin.to_string())?;
+        if let Some(cluster) = self.cluster.clone() {
+            self.data("cluster", &cluster)?;
+        }
+        writeln!(self.writer, "    </node>")
+    }
+
+    fn edge(&mut self, from: &Object, reference: &Reference) -> io::Result<()> {
+        let source = node_id(&from.id);
+        let target = node_id(&ObjectId::from(reference));
+        writeln!(self.writer, "    <edge source=\"{}\" target=\"{}\">", source, target)?;
+        self.data("kind", &reference.kind.to_string())?;
+        self.data("count", &reference.count.to_string())?;
+        writeln!(self.writer, "    </edge>")
+    }
+
+    fn end(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "  </graph>")?;
+        writeln!(self.writer, "</graphml>")
+    }
+}
+
+/// Write the graph of the objects in GraphML
+pub fn generate_graphml<W: Write>(db: &Database, options: &GraphOptions, writer: W) -> io::Result<()> {
+    walk(db, options, &mut GraphmlExporter { db, writer, cluster: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::database;
+    use crate::export::Clustering;
+
+    #[test]
+    fn test_graphml() {
+        let db = database("/type/q Quarter\nq/one\n  item/a Fix <b>\"it\"</b> & ship\nitem/b See item/a\n");
+        let mut output = Vec::new();
+        let options = GraphOptions { clustering: Clustering::Parent, ..GraphOptions::default() };
+        generate_graphml(&db, &options, &mut output).unwrap();
+        let graphml = String::from_utf8(output).unwrap();
+
+        assert!(graphml.contains("<key id=\"count\" for=\"edge\" attr.name=\"count\" attr.type=\"int\"/>"), "{}", graphml);
+        assert!(graphml.contains(
+            "    <node id=\"item/a\">\n      <data key=\"type\">item</data>\n      <data key=\"ident\">a</data>\n"
+        ), "{}", graphml);
+        assert!(graphml.contains("<data key=\"contents\">Fix &lt;b&gt;&quot;it&quot;&lt;/b&gt; &amp; ship</data>"), "{}", graphml);
+        assert!(graphml.contains("<data key=\"cluster\">q/one</data>"), "{}", graphml);
+        assert!(graphml.contains(
+            "    <edge source=\"item/b\" target=\"item/a\">\n      <data key=\"kind\">mention</data>\n      <data key=\"count\">1</data>\n"
+        ), "{}", graphml);
+        assert!(graphml.ends_with("  </graph>\n</graphml>\n"), "{}", graphml);
+    }
+}
@@ -0,0 +1,157 @@
+//! Mermaid flowcharts, e.g. for Markdown documentation
+//!
+//! Nodes are numbered, since Mermaid IDs cannot hold every identifier, and
+//! labels use entity codes for the characters Mermaid would interpret. Classes
+//! are named after types with a prefix, since types may be keywords like `end`.
+
+use super::{truncate, type_styles, walk, Cluster, Exporter, GraphOptions};
+use crate::ast::{EdgeKind, Object, ObjectId, Reference};
+use crate::database::Database;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Escape text for a quoted Mermaid label
+fn escape(text: &str) -> String {
+    text.replace('#', "#35;")
+        .replace('"', "#34;")
+        .replace('&', "#38;")
+        .replace('<', "#60;")
+        .replace('>', "#62;")
+        .replace('\n', "<br/>")
+}
+
+/// Get the name of the class of the nodes of a type
+fn class_name(type_name: &str) -> String {
+    format!("t_{}", type_name)
+}
+
+/// The writer of a graph as a Mermaid flowchart
+struct MermaidExporter<'a, W: Write> {
+    db: &'a Database,
+    options: &'a GraphOptions,
+    writer: W,
+    node_ids: HashMap<ObjectId, String>,
+    clusters: usize,
+    indent: &'static str,
+}
+
+impl<W: Write> Exporter for MermaidExporter<'_, W> {
+    fn begin(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "flowchart {}", self.options.rankdir)?;
+        for (type_name, style) in type_styles(self.db, self.options) {
+            let fill = style.iter().find(|(name, _)| name == "fillcolor").map_or("white", |(_, value)| value);
+            writeln!(self.writer, "  classDef {} fill:{}", class_name(&type_name), fill)?;
+        }
+        Ok(())
+    }
+
+    fn begin_cluster(&mut self, cluster: &Cluster) -> io::Result<()> {
+        writeln!(self.writer, "  subgraph c{} [\"{}\"]", self.clusters, escape(&cluster.label))?;
+        self.clusters += 1;
+        self.indent = "    ";
+        Ok(())
+    }
+
+    fn end_cluster(&mut self) -> io::Result<()> {
+        self.indent = "  ";
+        writeln!(self.writer, "  end")
+    }
+
+    fn node(&mut self, obj: &Object) -> io::Result<()> {
+        let id = &obj.id;
+        let node_id = format!("n{}", self.node_ids.len());
+        let rendered = self.db.render_system.render(&id.type_name, id.ident.as_deref().unwrap_or(""));
+        let mut label = format!("<b>{}</b>", escape(&rendered));
+        if !obj.contents.is_empty() {
+            label.push_str(&format!("<br/>{}", escape(&truncate(&obj.contents, self.options.max_label_len))));
+        }
+        writeln!(self.writer, "{}{}[\"{}\"]:::{}", self.indent, node_id, label, class_name(&id.type_name))?;
+
+        // Objects created for references are only placeholders
+        if obj.is_placeholder() {
+            writeln!(self.writer, "{}style {} stroke-dasharray: 4 4", self.indent, node_id)?;
+        }
+        self.node_ids.insert(id.clone(), node_id);
+        Ok(())
+    }
+
+    fn edge(&mut self, from: &Object, reference: &Reference) -> io::Result<()> {
+        let arrow = match reference.kind {
+            EdgeKind::Parent => "==>",
+            EdgeKind::Mention => "-->",
+            EdgeKind::MacroGenerated => "-.->",
+        };
+        let label = if reference.count > 1 { format!("|{}|", reference.count) } else { String::new() };
+        writeln!(
+            self.writer,
+            "  {} {}{} {}",
+            self.node_ids[&from.id],
+            arrow,
+            label,
+            self.node_ids[&ObjectId::from(reference)]
+        )
+    }
+
+    fn end(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Write the graph of the objects as a Mermaid flowchart
+pub fn generate_mermaid<W: Write>(db: &Database, options: &GraphOptions, writer: W) -> io::Result<()> {
+    let mut exporter = MermaidExporter { db, options, writer, node_ids: HashMap::new(), clusters: 0, indent: "  " };
+    walk(db, options, &mut exporter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::database;
+    use crate::export::Clustering;
+
+    #[test]
+    fn test_mermaid() {
+        let db = database("/type/q Quarter\n/lazytype/u User\n/render u <@\\1>\nq/one\n  item/a Fix \"it\" with u/joe & u/joe\n");
+        let mut output = Vec::new();
+        let options = GraphOptions { clustering: Clustering::Parent, ..GraphOptions::default() };
+        generate_mermaid(&db, &options, &mut output).unwrap();
+        let mermaid = String::from_utf8(output).unwrap();
+
+        assert!(mermaid.starts_with("flowchart LR\n  classDef t_item fill:"), "{}", mermaid);
+        assert!(mermaid.contains("  subgraph c0 [\"q/one\"]\n    n0[\"<b>item/a</b><br/>Fix #34;it#34; with u/joe #38; u/joe\"]:::t_item\n"), "{}", mermaid);
+        assert!(mermaid.contains("  n2[\"<b>#60;@joe#62;</b>\"]:::t_u\n  style n2 stroke-dasharray: 4 4\n"), "{}", mermaid);
+        assert!(mermaid.contains("  n0 ==> n1\n  n0 -->|2| n2\n"), "{}", mermaid);
+        assert_eq!(escape("#1 <a>\n&"), "#35;1 #60;a#62;<br/>#38;");
+    }
+
+    #[test]
+    fn test_keyword_types() {
+        let db = database("/type/end End
+/type/class Class
+/lazytype/default Default
+end/a For default/b
+class/c
+");
+        let mut output = Vec::new();
+        generate_mermaid(&db, &GraphOptions::default(), &mut output).unwrap();
+        let mermaid = String::from_utf8(output).unwrap();
+
+        for type_name in ["end", "class", "default"] {
+            assert!(mermaid.contains(&format!("  classDef t_{} fill:", type_name)), "{}", mermaid);
+            assert!(mermaid.contains(&format!(":::t_{}\n", type_name)), "{}", mermaid);
+        }
+        assert!(!mermaid.contains("classDef end"), "{}", mermaid);
+    }
+
+    #[test]
+    fn test_dangling_reference() {
+        let mut db = database("/type/t Task\nt/a A\n");
+        let a = ObjectId { type_name: "t".to_string(), ident: Some("a".to_string()) };
+        db.objects.get_mut(&a).unwrap().refs.insert(Reference::new("t", "gone", EdgeKind::Mention));
+
+        let mut output = Vec::new();
+        generate_mermaid(&db, &GraphOptions::default(), &mut output).unwrap();
+        let mermaid = String::from_utf8(output).unwrap();
+        assert!(!mermaid.contains("-->"), "{}", mermaid);
+    }
+}
@@ -0,0 +1,357 @@
+//! Exporting the graph of the objects to other tools
+//!
+//! Each format implements `Exporter`, whose methods `walk` calls for the
+//! graph, its clusters, its nodes and its edges in turn, so that they all
+//! draw the same objects, filtered and grouped the same way:
+//!
+//! ```text
+//! begin
+//!   begin_cluster, node..., end_cluster   (or just node... without clusters)
+//!   edge...
+//! end
+//! ```
+
+mod cypher;
+mod graphml;
+mod mermaid;
+
+pub use cypher::generate_cypher;
+pub use graphml::generate_graphml;
+pub use mermaid::generate_mermaid;
+
+use crate::ast::{Object, ObjectId, Reference};
+use crate::database::Database;
+use crate::error::Error;
+use crate::macros::StyleAttributes;
+use crate::parser::parse_file;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Fill colors given to the types without one in their style, in order
+const PALETTE: &[&str] = &[
+    "#E6F3FF", "#FFF9C4", "#C8E6C9", "#FFCDD2", "#E1BEE7", "#FFE0B2",
+    "#B2EBF2", "#F0F4C3", "#D7CCC8", "#F8BBD0", "#C5CAE9", "#EEEEEE",
+];
+
+/// How objects are grouped into clusters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Clustering {
+    /// No clusters
+    #[default]
+    None,
+
+    /// One cluster per type
+    Type,
+
+    /// One cluster per top-level object with the objects indented or
+    /// imported under it, e.g. one per quarter
+    Parent,
+}
+
+/// Options controlling the graph
+#[derive(Debug, Clone)]
+pub struct GraphOptions {
+    /// The number of characters of the contents shown in labels, counting
+    /// each grapheme, like an emoji, as one
+    pub max_label_len: usize,
+
+    /// The direction of the layout (LR, RL, TB or BT)
+    pub rankdir: String,
+
+    /// How objects are grouped into clusters
+    pub clustering: Clustering,
+
+    /// Node attributes of each type, overriding those of the `/style` rules
+    pub styles: HashMap<String, StyleAttributes>,
+
+    /// The objects to draw, with the links between them, or `None` for all
+    pub objects: Option<BTreeSet<ObjectId>>,
+}
+
+impl Default for GraphOptions {
+    fn default() -> Self {
+        Self {
+            max_label_len: 40,
+            rankdir: "LR".to_string(),
+            clustering: Clustering::None,
+            styles: HashMap::new(),
+            objects: None,
+        }
+    }
+}
+
+impl GraphOptions {
+    /// Read the `/style` rules of a file into the styles, e.g. to share them
+    /// between databases
+    pub fn load_styles<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let (_, _, render_system) = parse_file(path)?;
+        for (type_name, attributes) in render_system.styles() {
+            merge_attributes(self.styles.entry(type_name.clone()).or_default(), attributes);
+        }
+        Ok(())
+    }
+
+    /// Check whether an object is drawn
+    pub fn shows(&self, id: &ObjectId) -> bool {
+        self.objects.as_ref().is_none_or(|objects| objects.contains(id))
+    }
+}
+
+/// A group of nodes drawn together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cluster {
+    /// A name for the cluster, unique in the graph
+    pub name: String,
+
+    /// The text describing the cluster
+    pub label: String,
+}
+
+/// A format of graphs, written by `walk`
+pub trait Exporter {
+    /// Start the graph
+    fn begin(&mut self) -> io::Result<()>;
+
+    /// Start a cluster, whose nodes follow
+    fn begin_cluster(&mut self, _cluster: &Cluster) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// End the current cluster
+    fn end_cluster(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Write the node of an object
+    fn node(&mut self, object: &Object) -> io::Result<()>;
+
+    /// Write the edge of a reference of an object, once all the nodes are.
+    /// The object referred to always has a node.
+    fn edge(&mut self, from: &Object, reference: &Reference) -> io::Result<()>;
+
+    /// End the graph
+    fn end(&mut self) -> io::Result<()>;
+}
+
+/// Write the graph of the objects shown by the options, in sorted order,
+/// grouped into clusters as the options say
+pub fn walk<E: Exporter + ?Sized>(db: &Database, options: &GraphOptions, exporter: &mut E) -> io::Result<()> {
+    let mut objects: Vec<&Object> = db.objects.values().filter(|o| options.shows(&o.id)).collect();
+    objects.sort_by(|a, b| a.id.cmp(&b.id));
+
+    exporter.begin()?;
+
+    // Nodes come before any edge, so that they belong to their cluster
+    match options.clustering {
+        Clustering::None => {
+            for object in &objects {
+                exporter.node(object)?;
+            }
+        }
+        Clustering::Type => {
+            let mut types: BTreeMap<&str, Vec<&Object>> = BTreeMap::new();
+            for object in &objects {
+                types.entry(&object.id.type_name).or_default().push(object);
+            }
+            for (type_name, members) in types {
+                let label = db.types.get(type_name).map_or(type_name, |t| t.contents.as_str());
+                exporter.begin_cluster(&Cluster { name: type_name.to_string(), label: label.to_string() })?;
+                for object in members {
+                    exporter.node(object)?;
+                }
+                exporter.end_cluster()?;
+            }
+        }
+        Clustering::Parent => {
+            let mut trees: BTreeMap<ObjectId, Vec<&Object>> = BTreeMap::new();
+            for object in &objects {
+                trees.entry(root_of(db, &object.id)).or_default().push(object);
+            }
+            for (root, members) in trees {
+                // Objects on their own are not worth a cluster
+                let clustered = members.len() > 1 || members[0].id != root;
+                let ident = root.ident.as_deref().unwrap_or("");
+                if clustered {
+                    let label = db.render_system.render(&root.type_name, ident);
+                    exporter.begin_cluster(&Cluster { name: format!("{}/{}", root.type_name, ident), label })?;
+                }
+                for object in members {
+                    exporter.node(object)?;
+                }
+                if clustered {
+                    exporter.end_cluster()?;
+                }
+            }
+        }
+    }
+
+    // A database loaded from a file may have references to objects it does
+    // not contain, which have no node to draw an edge to
+    let has_node = |id: &ObjectId| db.objects.contains_key(id) && options.shows(id);
+    for object in &objects {
+        let mut refs: Vec<&Reference> = object.refs.iter().filter(|r| has_node(&ObjectId::from(*r))).collect();
+        refs.sort();
+        for reference in refs {
+            exporter.edge(object, reference)?;
+        }
+    }
+
+    exporter.end()
+}
+
+/// Get the top-level object an object is indented or imported under, or the
+/// object itself if it has no parent
+fn root_of(db: &Database, id: &ObjectId) -> ObjectId {
+    let mut root = id.clone();
+    let mut seen = BTreeSet::new();
+    while let Some(parent) = db.parent_of(&root) {
+        if !seen.insert(root.clone()) {
+            break;
+        }
+        root = parent;
+    }
+    root
+}
+
+/// Get the first characters of a text, with an ellipsis if it is longer
+pub(crate) fn truncate(text: &str, max_len: usize) -> String {
+    match text.grapheme_indices(true).nth(max_len) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Override attributes with others of the same name, and add the others
+fn merge_attributes(attributes: &mut StyleAttributes, overrides: &StyleAttributes) {
+    for (name, value) in overrides {
+        attributes.retain(|(n, _)| n != name);
+        attributes.push((name.clone(), value.clone()));
+    }
+}
+
+/// Get the node attributes of each type of objects: those of its style
+/// rules, and a fill color from the palette if they have none
+pub(crate) fn type_styles(db: &Database, options: &GraphOptions) -> BTreeMap<String, StyleAttributes> {
+    let mut styles = BTreeMap::new();
+    for object in db.objects.values() {
+        styles.entry(object.id.type_name.clone()).or_insert_with(StyleAttributes::new);
+    }
+
+    let mut palette = PALETTE.iter().cycle();
+    for (type_name, attributes) in styles.iter_mut() {
+        if let Some(style) = db.render_system.style(type_name) {
+            merge_attributes(attributes, style);
+        }
+        if let Some(style) = options.styles.get(type_name) {
+            merge_attributes(attributes, style);
+        }
+        if !attributes.iter().any(|(name, _)| name == "fillcolor") {
+            attributes.push(("fillcolor".to_string(), palette.next().unwrap().to_string()));
+        }
+    }
+    styles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::EdgeKind;
+    use crate::database::tests::database;
+
+    /// An exporter listing the calls of the walk
+    struct Calls(Vec<String>);
+
+    impl Exporter for Calls {
+        fn begin(&mut self) -> io::Result<()> {
+            self.0.push("begin".to_string());
+            Ok(())
+        }
+
+        fn begin_cluster(&mut self, cluster: &Cluster) -> io::Result<()> {
+            self.0.push(format!("cluster {} {}", cluster.name, cluster.label));
+            Ok(())
+        }
+
+        fn end_cluster(&mut self) -> io::Result<()> {
+            self.0.push("end cluster".to_string());
+            Ok(())
+        }
+
+        fn node(&mut self, object: &Object) -> io::Result<()> {
+            self.0.push(format!("node {}", object.id.ident.as_deref().unwrap()));
+            Ok(())
+        }
+
+        fn edge(&mut self, from: &Object, reference: &Reference) -> io::Result<()> {
+            self.0.push(format!("edge {} {} {}", from.id.ident.as_deref().unwrap(), reference.ident, reference.kind));
+            Ok(())
+        }
+
+        fn end(&mut self) -> io::Result<()> {
+            self.0.push("end".to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        let db = database("/type/q Quarter\n/type/t Task\nq/one\n  t/a A\nt/c C for t/a\n");
+        let walk = |options: &GraphOptions| {
+            let mut calls = Calls(Vec::new());
+            walk(&db, options, &mut calls).unwrap();
+            calls.0
+        };
+
+        assert_eq!(
+            walk(&GraphOptions { clustering: Clustering::Type, ..GraphOptions::default() }),
+            vec![
+                "begin", "cluster q Quarter", "node one", "end cluster", "cluster t Task", "node a", "node c",
+                "end cluster", "edge a one parent", "edge c a mention", "end",
+            ]
+        );
+
+        let objects = BTreeSet::from([ObjectId { type_name: "t".to_string(), ident: Some("a".to_string()) }]);
+        assert_eq!(
+            walk(&GraphOptions { clustering: Clustering::Parent, objects: Some(objects), ..GraphOptions::default() }),
+            vec!["begin", "cluster q/one q/one", "node a", "end cluster", "end"]
+        );
+    }
+
+    #[test]
+    fn test_walk_skips_dangling_references() {
+        let mut db = database("/type/t Task\nt/a A\n");
+        let a = ObjectId { type_name: "t".to_string(), ident: Some("a".to_string()) };
+        db.objects.get_mut(&a).unwrap().refs.insert(Reference::new("t", "gone", EdgeKind::Mention));
+
+        let mut calls = Calls(Vec::new());
+        walk(&db, &GraphOptions::default(), &mut calls).unwrap();
+        assert_eq!(calls.0, vec!["begin", "node a", "end"]);
+    }
+
+    #[test]
+    fn test_type_styles() {
+        let db = database("/type/p Project\n/lazytype/u User\n/type/t Task\n/style p shape=component fillcolor=\"#EEEEEE\"\n/style u shape=ellipse\np/a A\nt/b B by u/joe for p/a\n");
+        let mut options = GraphOptions::default();
+        options.styles.insert("u".to_string(), vec![("color".to_string(), "red".to_string())]);
+        let styles = type_styles(&db, &options);
+
+        let attributes = |pairs: &[(&str, &str)]| -> StyleAttributes {
+            pairs.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect()
+        };
+        assert_eq!(styles["p"], attributes(&[("shape", "component"), ("fillcolor", "#EEEEEE")]));
+        // The palette colors the unstyled types in order of their names
+        assert_eq!(styles["t"], attributes(&[("fillcolor", PALETTE[0])]));
+        assert_eq!(styles["u"], attributes(&[("shape", "ellipse"), ("color", "red"), ("fillcolor", PALETTE[1])]));
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("h\u{e9}llo w\u{f6}rld", 5), "h\u{e9}llo...");
+        // A family emoji is several code points but one character
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(truncate(&format!("{}{}{}", family, family, family), 2), format!("{}{}...", family, family));
+    }
+}
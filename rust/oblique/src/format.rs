@@ -0,0 +1,299 @@
+//! Formatter for the Oblique language
+
+use crate::ast::Span;
+use crate::cst::{Cst, CstLine};
+use crate::lexer::Token;
+use crate::macros::MacroSystem;
+
+/// Options controlling the layout of formatted files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// The number of spaces per level of indentation
+    pub indent_width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self { indent_width: 2 }
+    }
+}
+
+/// The directives of the block at the top of a file, in canonical order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Directive {
+    Type,
+    LazyType,
+    Ignore,
+    Macro,
+    Render,
+    Style,
+}
+
+/// What a line contains, as far as the layout is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Blank,
+    Comment,
+    Directive(Directive),
+    /// An import, which may be indented under an object
+    Import,
+    /// A line defining an object, which is the parent of the lines indented
+    /// under it
+    Object,
+}
+
+/// A line with its normalized text, without indentation
+#[derive(Debug, Clone)]
+struct Line {
+    kind: LineKind,
+    /// The width of the original indentation, in bytes like `parse_string`
+    indent: usize,
+    depth: usize,
+    text: String,
+}
+
+/// A directive of the block at the top of a file, with the comments and blank
+/// lines before it
+struct Unit {
+    leading: Vec<Line>,
+    line: Line,
+}
+
+/// Format Oblique source text.
+///
+/// Indentation is rebuilt from the hierarchy of the objects, the head of each
+/// line is separated from the rest by a single space, and the block of type
+/// declarations, macros and render rules at the top of the file is put in
+/// canonical order, unless reordering would change how macros expand. Contents
+/// and comments are kept as written, and so are the line endings.
+pub fn format_source(source: &str, options: &FormatOptions) -> String {
+    let cst = Cst::parse(source);
+    let mut lines: Vec<Line> = cst.lines.iter().map(|line| classify_line(&cst, line)).collect();
+    compute_depths(&mut lines);
+
+    // Split off the directive block, and the comments of the file before it
+    let block_len = lines
+        .iter()
+        .take_while(|l| matches!(l.kind, LineKind::Blank | LineKind::Comment | LineKind::Directive(_)))
+        .count();
+    let rest = lines.split_off(block_len);
+    let mut units = Vec::new();
+    let mut leading = Vec::new();
+    for line in lines {
+        match line.kind {
+            LineKind::Directive(_) => units.push(Unit { leading: std::mem::take(&mut leading), line }),
+            _ => leading.push(line),
+        }
+    }
+    let mut header = Vec::new();
+    if let Some(first) = units.first_mut() {
+        if let Some(blank) = first.leading.iter().rposition(|l| l.kind == LineKind::Blank) {
+            header = first.leading.drain(..=blank).collect();
+        }
+    }
+
+    let original: Vec<usize> = (0..units.len()).collect();
+    let mut order = original.clone();
+    order.sort_by_key(|&i| directive(&units[i].line));
+    if expand(&units, &order).is_none() || expand(&units, &order) != expand(&units, &original) {
+        order = original;
+    }
+
+    let ordered = header
+        .iter()
+        .chain(order.into_iter().flat_map(|i| units[i].leading.iter().chain(std::iter::once(&units[i].line))))
+        .chain(leading.iter())
+        .chain(rest.iter());
+    // Files with CRLF line endings keep them
+    let newline = cst
+        .lines
+        .iter()
+        .map(|line| cst.text(line.terminator()))
+        .find(|terminator| !terminator.is_empty())
+        .unwrap_or("\n");
+    render(ordered, newline, options)
+}
+
+/// Get the directive of a directive line
+fn directive(line: &Line) -> Option<Directive> {
+    match line.kind {
+        LineKind::Directive(directive) => Some(directive),
+        _ => None,
+    }
+}
+
+/// Classify a line and normalize its text
+fn classify_line(cst: &Cst, line: &CstLine) -> Line {
+    let text = cst.line_text(line).trim();
+    let (kind, text) = match &line.tokens[0].token {
+        Token::Eol => (LineKind::Blank, String::new()),
+        Token::Comment(_) => (LineKind::Comment, text.to_string()),
+        // Contents are taken verbatim by the parser too
+        Token::TypeDecl(_) => (LineKind::Directive(Directive::Type), join_head(cst, line)),
+        Token::LazyTypeDecl(_) => (LineKind::Directive(Directive::LazyType), join_head(cst, line)),
+        Token::IgnoreTypeDecl(_) => (LineKind::Directive(Directive::Ignore), join_head(cst, line)),
+        // Replacements and templates are taken verbatim by the parser
+        Token::MacroDecl => (LineKind::Directive(Directive::Macro), join_keyword(text, "/macro")),
+        Token::RenderDecl => (LineKind::Directive(Directive::Render), join_keyword(text, "/render")),
+        Token::StyleDecl => (LineKind::Directive(Directive::Style), join_keyword(text, "/style")),
+        Token::ImportDecl => (LineKind::Import, text.split_whitespace().collect::<Vec<_>>().join(" ")),
+        _ => (LineKind::Object, join_head(cst, line)),
+    };
+    Line { kind, indent: line.indent.len(), depth: 0, text }
+}
+
+/// Normalize the spacing of a type declaration or object line: one space after
+/// its first token where there was whitespace, and the rest of the line verbatim
+fn join_head(cst: &Cst, line: &CstLine) -> String {
+    let head = line.tokens[0].span;
+    let rest = cst.text(Span::new(head.end, line.span.end)).trim_end();
+    let contents = rest.trim_start();
+    if contents.len() == rest.len() || contents.is_empty() {
+        format!("{}{}", cst.text(head), contents)
+    } else {
+        format!("{} {}", cst.text(head), contents)
+    }
+}
+
+/// Normalize the spacing of a `/macro`, `/render` or `/style` line: one space after the
+/// keyword and after its first argument, and the rest of the line verbatim
+fn join_keyword(line: &str, keyword: &str) -> String {
+    let rest = line[keyword.len()..].trim_start();
+    match rest.find(char::is_whitespace) {
+        Some(idx) => format!("{} {} {}", keyword, &rest[..idx], rest[idx..].trim_start()),
+        None if rest.is_empty() => keyword.to_string(),
+        None => format!("{} {}", keyword, rest),
+    }
+}
+
+/// Compute the depth of each line in the object hierarchy, the way
+/// `parse_string` finds the parent of a line from its indentation. Comments
+/// get the depth of the line after them.
+fn compute_depths(lines: &mut [Line]) {
+    // Stack of (indentation, depth) of the objects lines may be nested in
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    for line in lines.iter_mut() {
+        if matches!(line.kind, LineKind::Blank | LineKind::Comment) {
+            continue;
+        }
+        let indent = line.indent;
+        while stack.last().is_some_and(|(i, _)| *i >= indent) {
+            stack.pop();
+        }
+        line.depth = stack.last().map_or(0, |(_, depth)| depth + 1);
+        if line.kind == LineKind::Object {
+            stack.push((indent, line.depth));
+        }
+    }
+
+    let mut depth = 0;
+    for line in lines.iter_mut().rev() {
+        match line.kind {
+            LineKind::Blank => {}
+            LineKind::Comment => line.depth = depth,
+            _ => depth = line.depth,
+        }
+    }
+}
+
+/// Expand the directives taken in some order, macros applying to the lines
+/// after them, and get the expansions in the original order
+fn expand(units: &[Unit], order: &[usize]) -> Option<Vec<String>> {
+    let mut macros = MacroSystem::new();
+    let mut expanded = vec![String::new(); units.len()];
+    for &i in order {
+        let line = macros.apply(&units[i].line.text);
+        if units[i].line.kind == LineKind::Directive(Directive::Macro) {
+            let rest = line.strip_prefix("/macro")?.trim_start();
+            let (pattern, replacement) = rest.split_once(char::is_whitespace)?;
+            macros.add_macro(pattern, replacement.trim_start()).ok()?;
+        }
+        expanded[i] = line;
+    }
+    Some(expanded)
+}
+
+/// Render lines, indenting them and collapsing runs of blank lines
+fn render<'a>(lines: impl Iterator<Item = &'a Line>, newline: &str, options: &FormatOptions) -> String {
+    let mut output = String::new();
+    let mut pending_blank = false;
+    for line in lines {
+        if line.kind == LineKind::Blank {
+            pending_blank = !output.is_empty();
+            continue;
+        }
+        if pending_blank {
+            output.push_str(newline);
+            pending_blank = false;
+        }
+        output.push_str(&" ".repeat(line.depth * options.indent_width));
+        output.push_str(&line.text);
+        output.push_str(newline);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        format_source(source, &FormatOptions::default())
+    }
+
+    #[test]
+    fn test_spacing_and_indentation() {
+        let source = "\n\n/type/t   Task\nt/a    Task  A  for Foo/bar\n      t/b   Child\n        t/c  Grandchild   # note\n   t/d Child\n\n\n\nitem/x   Top\n";
+        assert_eq!(
+            format(source),
+            "/type/t Task\nt/a Task  A  for Foo/bar\n  t/b Child\n    t/c Grandchild   # note\n  t/d Child\n\nitem/x Top\n"
+        );
+        let wide = format_source(source, &FormatOptions { indent_width: 4 });
+        assert!(wide.contains("\n        t/c Grandchild"), "{}", wide);
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let source = "/type/t Task\r\n\r\n  t/a   Task  A\r\nitem/x t/a).\r\n";
+        assert_eq!(format(source), "/type/t Task\r\n\r\nt/a Task  A\r\nitem/x t/a).\r\n");
+        assert_eq!(format("item/x)\n"), "item/x)\n");
+    }
+
+    #[test]
+    fn test_comments_and_imports() {
+        let source = "q/q1 First\n        # About the import\n    /import   --vocab  team.oblique\n  # Trailing\nitem/x\n";
+        assert_eq!(
+            format(source),
+            "q/q1 First\n  # About the import\n  /import --vocab team.oblique\n# Trailing\nitem/x\n"
+        );
+    }
+
+    #[test]
+    fn test_verbatim_templates() {
+        let source = "/render   t   Task  #\\1\n/macro   \\b([a-z]+)@   u/\\1   and  more\n";
+        assert_eq!(format(source), "/macro \\b([a-z]+)@ u/\\1   and  more\n/render t Task  #\\1\n");
+    }
+
+    #[test]
+    fn test_directive_order() {
+        let source = "# Title\n\n/render u @\\1\n# Users\n/lazytype/u User\n/macro ([a-z]+)@ u/\\1\n/type/t Task\n\n# Tasks\nt/a For joe@\n";
+        assert_eq!(
+            format(source),
+            "# Title\n\n/type/t Task\n# Users\n/lazytype/u User\n/macro ([a-z]+)@ u/\\1\n/render u @\\1\n\n# Tasks\nt/a For joe@\n"
+        );
+    }
+
+    #[test]
+    fn test_directive_order_kept_when_macros_apply() {
+        // Moving the type after the macro would make the macro rewrite it
+        let source = "/macro Work Task\n/type/t Work\n";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_idempotent() {
+        let source = include_str!("../examples/nuclear_plant.oblique");
+        let formatted = format(source);
+        assert_eq!(format(&formatted), formatted);
+    }
+}
@@ -0,0 +1,286 @@
+//! Static HTML site of a database, rendering references with the `/render`
+//! templates
+//!
+//! The site has an index of the types, a page per type listing its objects,
+//! and a page per object with its contents, links and backlinks:
+//!
+//! ```text
+//! index.html
+//! <type>/index.html
+//! <type>/o/<ident>.html
+//! ```
+//!
+//! Object pages have a directory of their own, so that no identifier names the
+//! page of its type, and the characters of types and identifiers other than
+//! word characters and dashes are percent-encoded in file names, so that no
+//! page is written outside its directory.
+//!
+//! Templates are trusted HTML, and are inserted as they are, so the site is
+//! only as safe as the files declaring them.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::ast::{EdgeKind, Object, ObjectId, TypeFlavor};
+use crate::database::Database;
+use crate::lexer::{lex_line, Token};
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 50em; margin: 2em auto; padding: 0 1em; line-height: 1.5; }
+nav { font-size: 0.9em; margin-bottom: 1em; }
+code { color: #666; }
+a.ref { text-decoration: none; border-bottom: 1px dotted; }
+li .contents { color: #444; }
+.placeholder { color: #888; font-style: italic; }";
+
+/// Write the site of a database into a directory, creating it if needed
+pub fn generate_html(db: &Database, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("index.html"), index_page(db))?;
+
+    for type_name in type_names(db) {
+        let type_dir = dir.join(file_name(type_name));
+        fs::create_dir_all(type_dir.join("o"))?;
+        fs::write(type_dir.join("index.html"), type_page(db, type_name))?;
+    }
+
+    for object in db.objects.values() {
+        let ident = object.id.ident.as_deref().unwrap_or("");
+        let object_dir = dir.join(file_name(&object.id.type_name)).join("o");
+        fs::write(object_dir.join(format!("{}.html", file_name(ident))), object_page(db, object))?;
+    }
+    Ok(())
+}
+
+/// Get the names of the types with a page, in order: the declared ones, and
+/// those of objects of undeclared types
+fn type_names(db: &Database) -> BTreeSet<&str> {
+    db.types
+        .keys()
+        .chain(db.objects.keys().map(|id| &id.type_name))
+        .map(String::as_str)
+        .collect()
+}
+
+/// Get the description of a type, or its name if it is not declared
+fn type_label<'a>(db: &'a Database, type_name: &'a str) -> &'a str {
+    db.types.get(type_name).map_or(type_name, |t| t.contents.as_str())
+}
+
+/// Get the page listing the types
+fn index_page(db: &Database) -> String {
+    let mut body = String::from("<h1>Types</h1>\n<ul>\n");
+    for type_name in type_names(db) {
+        let count = db.objects.keys().filter(|id| id.type_name == type_name).count();
+        body.push_str(&format!(
+            "<li><a href=\"{}/index.html\">{}</a> <code>{}</code> ({} objects)</li>\n",
+            escape(&url_segment(type_name)),
+            escape(type_label(db, type_name)),
+            escape(type_name),
+            count
+        ));
+    }
+    body.push_str("</ul>\n");
+    page("Types", "", &body)
+}
+
+/// Get the page listing the objects of a type
+fn type_page(db: &Database, type_name: &str) -> String {
+    let label = type_label(db, type_name);
+    let mut body = format!("<h1>{} <code>{}</code></h1>\n<p>", escape(label), escape(type_name));
+    match db.types.get(type_name) {
+        Some(typ) => {
+            let flavor = match typ.flavor {
+                TypeFlavor::Strict => "strict",
+                TypeFlavor::Lazy => "lazy",
+                TypeFlavor::Ignore => "ignore",
+            };
+            body.push_str(&format!("A {} type", flavor));
+            if let Some(location) = &typ.location {
+                body.push_str(&format!(", declared at <code>{}</code>", escape(&location.to_string())));
+            }
+        }
+        None => body.push_str("An undeclared type"),
+    }
+    body.push_str(".</p>\n<ul>\n");
+
+    let mut objects: Vec<&Object> = db.objects.values().filter(|o| o.id.type_name == type_name).collect();
+    objects.sort_by(|a, b| definition_order(a, b));
+    for object in objects {
+        body.push_str(&format!("<li>{}</li>\n", summary(db, &object.id, "../")));
+    }
+    body.push_str("</ul>\n");
+    page(label, "../", &body)
+}
+
+/// Get the page of an object
+fn object_page(db: &Database, object: &Object) -> String {
+    let id = &object.id;
+    let ident = id.ident.as_deref().unwrap_or("");
+    let mut body = format!(
+        "<h1>{} <code>{}/{}</code></h1>\n",
+        db.render_system.render(&id.type_name, ident),
+        escape(&id.type_name),
+        escape(ident)
+    );
+    if object.is_placeholder() {
+        body.push_str("<p class=\"placeholder\">Created by reference, not defined.</p>\n");
+    } else {
+        body.push_str(&format!("<p>{}</p>\n", render_contents(db, &object.contents, "../../")));
+    }
+
+    body.push_str("<dl>\n");
+    body.push_str(&format!(
+        "<dt>Type</dt><dd><a href=\"../index.html\">{}</a></dd>\n",
+        escape(type_label(db, &id.type_name))
+    ));
+    if let Some(parent) = db.parent_of(id) {
+        body.push_str(&format!("<dt>Parent</dt><dd>{}</dd>\n", link(db, &parent, "../../")));
+    }
+    if let Some(location) = &object.location {
+        body.push_str(&format!("<dt>Defined at</dt><dd><code>{}</code></dd>\n", escape(&location.to_string())));
+    }
+    body.push_str("</dl>\n");
+
+    let children = db.children_of(id);
+    if !children.is_empty() {
+        body.push_str("<h2>Children</h2>\n<ul>\n");
+        for child in children {
+            body.push_str(&format!("<li>{}</li>\n", summary(db, child, "../../")));
+        }
+        body.push_str("</ul>\n");
+    }
+
+    // Children link to their parent, but only mentions are backlinks
+    let backlinks: Vec<&ObjectId> = db
+        .referrers(id)
+        .filter(|r| {
+            db.objects.get(r).is_some_and(|o| {
+                o.refs.iter().any(|x| x.kind != EdgeKind::Parent && ObjectId::from(x) == *id)
+            })
+        })
+        .collect();
+    if !backlinks.is_empty() {
+        body.push_str("<h2>Referenced by</h2>\n<ul>\n");
+        for referrer in backlinks {
+            body.push_str(&format!("<li>{}</li>\n", summary(db, referrer, "../../")));
+        }
+        body.push_str("</ul>\n");
+    }
+
+    page(&format!("{}/{}", id.type_name, ident), "../../", &body)
+}
+
+/// Render the contents of an object as HTML, replacing references by their
+/// rendering linked to their page
+fn render_contents(db: &Database, contents: &str, root: &str) -> String {
+    let mut html = String::new();
+    let mut end = 0;
+    for token in lex_line(contents) {
+        if let Token::Reference { type_name, ident } = &token.token {
+            html.push_str(&escape(&contents[end..token.span.start]));
+            let id = ObjectId { type_name: type_name.clone(), ident: Some(ident.clone()) };
+            html.push_str(&link(db, &id, root));
+            end = token.span.end;
+        }
+    }
+    html.push_str(&escape(&contents[end..]));
+    html
+}
+
+/// Get the link to the page of an object, showing its rendering. A rendering
+/// that is a link of its own is followed by a separate link to the page.
+fn link(db: &Database, id: &ObjectId, root: &str) -> String {
+    let ident = id.ident.as_deref().unwrap_or("");
+    let rendered = db.render_system.render(&id.type_name, ident);
+    if !db.objects.contains_key(id) {
+        return rendered;
+    }
+    let href = format!("{}{}/o/{}.html", root, escape(&url_segment(&id.type_name)), escape(&url_segment(ident)));
+    if rendered.contains("<a ") {
+        format!("{} <a class=\"ref\" href=\"{}\">&#x2197;</a>", rendered, href)
+    } else {
+        format!("<a class=\"ref\" href=\"{}\">{}</a>", href, rendered)
+    }
+}
+
+/// Get the link to an object followed by its contents, for lists
+fn summary(db: &Database, id: &ObjectId, root: &str) -> String {
+    let contents = db.objects.get(id).map_or("", |o| o.contents.as_str());
+    format!(
+        "{} <span class=\"contents\">{}</span>",
+        link(db, id, root),
+        render_contents(db, contents, root)
+    )
+}
+
+/// Compare objects by where they are defined, those created by reference last
+fn definition_order(a: &Object, b: &Object) -> std::cmp::Ordering {
+    let key = |o: &Object| (o.location.is_none(), o.location.clone());
+    key(a).cmp(&key(b)).then_with(|| a.id.cmp(&b.id))
+}
+
+/// Get the name of the file or directory of a type or an identifier,
+/// percent-encoding the characters other than word characters and dashes
+fn file_name(name: &str) -> String {
+    let mut encoded = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() || c == '_' || c == '-' {
+            encoded.push(c);
+        } else {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    encoded
+}
+
+/// Get the URL path segment of the file or directory of a type or an
+/// identifier, which browsers decode back to the file name
+fn url_segment(name: &str) -> String {
+    file_name(name).replace('%', "%25")
+}
+
+/// Get a whole page
+fn page(title: &str, root: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n<nav><a href=\"{}index.html\">All types</a></nav>\n{}</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        root,
+        body
+    )
+}
+
+/// Escape text for HTML
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::database;
+
+    #[test]
+    fn test_render_contents() {
+        let db = database("/type/t Task\n/lazytype/b Bug\n/render t Task #\\1\n/render b <a href=\"http://bugs/\\1\">\\1</a>\nt/a Fix b/12 & <x> (t/b)\nt/b Other\n");
+        assert_eq!(
+            render_contents(&db, &db.objects[&ObjectId { type_name: "t".to_string(), ident: Some("a".to_string()) }].contents, "../"),
+            "Fix <a href=\"http://bugs/12\">12</a> <a class=\"ref\" href=\"../b/o/12.html\">&#x2197;</a> &amp; &lt;x&gt; (<a class=\"ref\" href=\"../t/o/b.html\">Task #b</a>)"
+        );
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("zelda_2-b"), "zelda_2-b");
+        assert_eq!(file_name("../a/b"), "%2E%2E%2Fa%2Fb");
+        assert_eq!(url_segment("a.b"), "a%252Eb");
+    }
+}
@@ -3,6 +3,8 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use crate::ast::Span;
+
 /// Token types for the Oblique lexer
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
@@ -33,97 +35,135 @@ pub enum Token {
     /// A render declaration (/render)
     RenderDecl,
 
+    /// A style declaration (/style)
+    StyleDecl,
+
     /// A comment (# text)
     Comment(String),
 
     /// End of line
-    EOL,
+    Eol,
+}
+
+/// A token with the bytes it spans in its line
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
 }
 
 lazy_static! {
-    static ref WORD_RE: Regex = Regex::new(r"^[^\s/]+").unwrap();
-    static ref REFERENCE_RE: Regex = Regex::new(r"^([a-z]+)/([^\s]+)").unwrap();
-    static ref AUTO_REFERENCE_RE: Regex = Regex::new(r"^([a-z]+)/\s").unwrap();
+    static ref WORD_RE: Regex = Regex::new(r"^\S+").unwrap();
+    static ref REFERENCE_RE: Regex = Regex::new(r"^([a-z]+)/([\w-]+)").unwrap();
+    static ref AUTO_REFERENCE_RE: Regex = Regex::new(r"^([a-z]+)/(\s|$)").unwrap();
     static ref TYPE_DECL_RE: Regex = Regex::new(r"^/type/([a-z]+)").unwrap();
     static ref LAZY_TYPE_DECL_RE: Regex = Regex::new(r"^/lazytype/([a-z]+)").unwrap();
     static ref IGNORE_TYPE_DECL_RE: Regex = Regex::new(r"^/ignore/([a-z]+)").unwrap();
     static ref MACRO_DECL_RE: Regex = Regex::new(r"^/macro\b").unwrap();
     static ref IMPORT_DECL_RE: Regex = Regex::new(r"^/import\b").unwrap();
     static ref RENDER_DECL_RE: Regex = Regex::new(r"^/render\b").unwrap();
+    static ref STYLE_DECL_RE: Regex = Regex::new(r"^/style\b").unwrap();
     static ref COMMENT_RE: Regex = Regex::new(r"^#(.*)$").unwrap();
 }
 
-/// Tokenize a line of Oblique code
-pub fn tokenize_line(line: &str) -> Vec<Token> {
+/// A token variant holding the name it was declared with
+type TokenConstructor = fn(String) -> Token;
+
+/// Punctuation that may follow a reference without being part of it
+const REFERENCE_PUNCTUATION: &[char] = &['(', ')', ';', ':', '.', ',', '!', '?'];
+
+/// Tokenize a line of Oblique code, with the span of each token in the line.
+///
+/// Tokens are separated by whitespace, which is not part of any token, so the
+/// line can be rebuilt exactly from the spans. The line ends with an empty
+/// `Eol` token.
+pub fn lex_line(line: &str) -> Vec<SpannedToken> {
     let mut tokens = Vec::new();
-    let mut remaining = line.trim_start();
+    let mut offset = 0;
+    loop {
+        let rest = &line[offset..];
+        let trimmed = rest.trim_start();
+        offset += rest.len() - trimmed.len();
+        if trimmed.is_empty() {
+            break;
+        }
 
-    // Empty line
-    if remaining.is_empty() {
-        tokens.push(Token::EOL);
-        return tokens;
+        let (token, len) = lex_token(trimmed);
+        tokens.push(SpannedToken { token, span: Span::new(offset, offset + len) });
+        offset += len;
     }
 
-    // Process tokens until the line is empty
-    while !remaining.is_empty() {
-        if remaining.starts_with(' ') {
-            remaining = remaining.trim_start();
-            continue;
+    tokens.push(SpannedToken { token: Token::Eol, span: Span::new(line.len(), line.len()) });
+    tokens
+}
+
+/// Get the token at the start of some text, which does not start with
+/// whitespace, with its length
+fn lex_token(text: &str) -> (Token, usize) {
+    // A comment runs to the end of the line
+    if let Some(captures) = COMMENT_RE.captures(text) {
+        return (Token::Comment(captures[1].to_string()), text.trim_end().len());
+    }
+
+    let declarations: [(&Regex, TokenConstructor); 3] = [
+        (&TYPE_DECL_RE, Token::TypeDecl),
+        (&LAZY_TYPE_DECL_RE, Token::LazyTypeDecl),
+        (&IGNORE_TYPE_DECL_RE, Token::IgnoreTypeDecl),
+    ];
+    for (regex, token) in declarations {
+        if let Some(captures) = regex.captures(text) {
+            return (token(captures[1].to_string()), captures[0].len());
         }
+    }
 
-        // Comment
-        if let Some(captures) = COMMENT_RE.captures(remaining) {
-            tokens.push(Token::Comment(captures[1].to_string()));
-            tokens.push(Token::EOL);
-            return tokens;
+    let commands: [(&Regex, Token); 4] = [
+        (&MACRO_DECL_RE, Token::MacroDecl),
+        (&IMPORT_DECL_RE, Token::ImportDecl),
+        (&RENDER_DECL_RE, Token::RenderDecl),
+        (&STYLE_DECL_RE, Token::StyleDecl),
+    ];
+    for (regex, token) in commands {
+        if let Some(found) = regex.find(text) {
+            return (token, found.len());
         }
+    }
+
+    if let Some(captures) = AUTO_REFERENCE_RE.captures(text) {
+        return (Token::AutoReference(captures[1].to_string()), captures[1].len() + 1);
+    }
+
+    // Punctuation before a reference is a word of its own, like "(" in "(t/a)"
+    let punctuation = text.len() - text.trim_start_matches(REFERENCE_PUNCTUATION).len();
+    if punctuation > 0 && matches!(lex_token(&text[punctuation..]).0, Token::Reference { .. }) {
+        return (Token::Word(text[..punctuation].to_string()), punctuation);
+    }
 
-        // Try to match each token type
-        if let Some(captures) = TYPE_DECL_RE.captures(remaining) {
-            tokens.push(Token::TypeDecl(captures[1].to_string()));
-            remaining = &remaining[captures[0].len()..];
-        } else if let Some(captures) = LAZY_TYPE_DECL_RE.captures(remaining) {
-            tokens.push(Token::LazyTypeDecl(captures[1].to_string()));
-            remaining = &remaining[captures[0].len()..];
-        } else if let Some(captures) = IGNORE_TYPE_DECL_RE.captures(remaining) {
-            tokens.push(Token::IgnoreTypeDecl(captures[1].to_string()));
-            remaining = &remaining[captures[0].len()..];
-        } else if let Some(captures) = MACRO_DECL_RE.captures(remaining) {
-            tokens.push(Token::MacroDecl);
-            remaining = &remaining[captures[0].len()..];
-        } else if let Some(captures) = IMPORT_DECL_RE.captures(remaining) {
-            tokens.push(Token::ImportDecl);
-            remaining = &remaining[captures[0].len()..];
-        } else if let Some(captures) = RENDER_DECL_RE.captures(remaining) {
-            tokens.push(Token::RenderDecl);
-            remaining = &remaining[captures[0].len()..];
-        } else if let Some(captures) = AUTO_REFERENCE_RE.captures(remaining) {
-            tokens.push(Token::AutoReference(captures[1].to_string()));
-            remaining = &remaining[captures[0].len()..];
-        } else if let Some(captures) = REFERENCE_RE.captures(remaining) {
-            tokens.push(Token::Reference {
+    // A reference may be followed by punctuation, but is otherwise a word of
+    // its own, like "a/b" in "a/b/c" or "Foo/bar" is not one
+    if let Some(captures) = REFERENCE_RE.captures(text) {
+        let len = captures[0].len();
+        if text[len..].chars().next().is_none_or(|c| c.is_whitespace() || REFERENCE_PUNCTUATION.contains(&c)) {
+            let token = Token::Reference {
                 type_name: captures[1].to_string(),
                 ident: captures[2].to_string(),
-            });
-            remaining = &remaining[captures[0].len()..];
-        } else if let Some(captures) = WORD_RE.captures(remaining) {
-            tokens.push(Token::Word(captures[0].to_string()));
-            remaining = &remaining[captures[0].len()..];
-        } else {
-            // If we can't match anything, just take the next character as a word
-            tokens.push(Token::Word(remaining[0..1].to_string()));
-            remaining = &remaining[1..];
+            };
+            return (token, len);
         }
     }
 
-    tokens.push(Token::EOL);
-    tokens
+    let len = WORD_RE.find(text).map_or(text.len(), |m| m.end());
+    (Token::Word(text[..len].to_string()), len)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Tokenize a line of Oblique code, without the spans
+    fn tokenize_line(line: &str) -> Vec<Token> {
+        lex_line(line).into_iter().map(|t| t.token).collect()
+    }
+
     #[test]
     fn test_tokenize_simple_words() {
         let tokens = tokenize_line("hello world");
@@ -132,7 +172,7 @@ mod tests {
             vec![
                 Token::Word("hello".to_string()),
                 Token::Word("world".to_string()),
-                Token::EOL
+                Token::Eol
             ]
         );
     }
@@ -147,7 +187,7 @@ mod tests {
                     type_name: "p".to_string(),
                     ident: "alpha".to_string()
                 },
-                Token::EOL
+                Token::Eol
             ]
         );
     }
@@ -165,7 +205,7 @@ mod tests {
                 },
                 Token::Word("needs".to_string()),
                 Token::Word("action".to_string()),
-                Token::EOL
+                Token::Eol
             ]
         );
     }
@@ -177,7 +217,7 @@ mod tests {
             vec![
                 Token::TypeDecl("p".to_string()),
                 Token::Word("Project".to_string()),
-                Token::EOL
+                Token::Eol
             ]
         );
         
@@ -186,7 +226,7 @@ mod tests {
             vec![
                 Token::LazyTypeDecl("u".to_string()),
                 Token::Word("User".to_string()),
-                Token::EOL
+                Token::Eol
             ]
         );
 
@@ -195,7 +235,7 @@ mod tests {
             vec![
                 Token::IgnoreTypeDecl("x".to_string()),
                 Token::Word("IgnoreMe".to_string()),
-                Token::EOL
+                Token::Eol
             ]
         );
     }
@@ -208,7 +248,7 @@ mod tests {
                 Token::MacroDecl,
                 Token::Word("pattern".to_string()),
                 Token::Word("replacement".to_string()),
-                Token::EOL
+                Token::Eol
             ]
         );
 
@@ -218,7 +258,17 @@ mod tests {
                 Token::RenderDecl,
                 Token::Word("p".to_string()),
                 Token::Word("<template>".to_string()),
-                Token::EOL
+                Token::Eol
+            ]
+        );
+
+        assert_eq!(
+            tokenize_line("/style p shape=note"),
+            vec![
+                Token::StyleDecl,
+                Token::Word("p".to_string()),
+                Token::Word("shape=note".to_string()),
+                Token::Eol
             ]
         );
 
@@ -227,7 +277,7 @@ mod tests {
             vec![
                 Token::ImportDecl,
                 Token::Word("file.oblique".to_string()),
-                Token::EOL
+                Token::Eol
             ]
         );
     }
@@ -238,7 +288,7 @@ mod tests {
             tokenize_line("# This is a comment"),
             vec![
                 Token::Comment(" This is a comment".to_string()),
-                Token::EOL
+                Token::Eol
             ]
         );
 
@@ -247,7 +297,7 @@ mod tests {
             vec![
                 Token::Word("content".to_string()),
                 Token::Comment(" comment".to_string()),
-                Token::EOL
+                Token::Eol
             ]
         );
     }
@@ -259,8 +309,33 @@ mod tests {
             vec![
                 Token::AutoReference("p".to_string()),
                 Token::Word("content".to_string()),
-                Token::EOL
+                Token::Eol
             ]
         );
     }
+
+    #[test]
+    fn test_tokenize_words_and_punctuation() {
+        assert_eq!(
+            tokenize_line("Component/Project (Lead, p/cooling). a/b/c"),
+            vec![
+                Token::Word("Component/Project".to_string()),
+                Token::Word("(Lead,".to_string()),
+                Token::Reference { type_name: "p".to_string(), ident: "cooling".to_string() },
+                Token::Word(").".to_string()),
+                Token::Word("a/b/c".to_string()),
+                Token::Eol
+            ]
+        );
+        assert_eq!(tokenize_line("t/"), vec![Token::AutoReference("t".to_string()), Token::Eol]);
+    }
+
+    #[test]
+    fn test_lex_spans() {
+        let line = "  t/a\tTask  é (u/joe) # note ";
+        let tokens = lex_line(line);
+        let texts: Vec<_> = tokens.iter().map(|t| t.span.slice(line)).collect();
+        assert_eq!(texts, vec!["t/a", "Task", "é", "(", "u/joe", ")", "# note", ""]);
+        assert_eq!(tokens[3].token, Token::Word("(".to_string()));
+    }
 }
@@ -6,13 +6,27 @@
 mod ast;
 mod error;
 mod lexer;
+mod cst;
 mod parser;
 mod database;
 mod macros;
+mod format;
+mod rename;
 pub mod dot;
+pub mod export;
+pub mod html;
+pub mod proto;
+pub mod query;
 
-pub use ast::{Type, TypeFlavor, Object, ObjectId, Reference};
-pub use error::Error;
-pub use database::Database;
-pub use parser::{parse_file, parse_string};
-pub use dot::generate_dot;
+pub use ast::{EdgeKind, Type, TypeFlavor, Object, ObjectId, ObjectOrigin, Reference, ReferenceOccurrence, SourceLocation, Span};
+pub use error::{Diagnostic, Error};
+pub use database::{AutoIdStrategy, Database, ResolveOptions};
+pub use lexer::{lex_line, SpannedToken, Token};
+pub use cst::{Cst, CstLine};
+pub use parser::{parse_file, parse_string, ImportOptions};
+pub use format::{format_source, FormatOptions};
+pub use rename::{apply_edits, rename, write_auto_ids, Rename, RenameTarget, TextEdit};
+pub use dot::{generate_dot, Clustering, DotOptions};
+pub use export::{generate_cypher, generate_graphml, generate_mermaid, walk, Cluster, Exporter, GraphOptions};
+pub use html::generate_html;
+pub use query::{parse_query, run_query, Query, QueryResult};
@@ -1,6 +1,7 @@
 //! Macro system for the Oblique language
 #![allow(dead_code)]
 
+use crate::ast::SourceLocation;
 use crate::error::Error;
 use regex::Regex;
 use std::collections::HashMap;
@@ -15,8 +16,14 @@ pub struct Macro {
     pub replacement: String,
 }
 
+impl PartialEq for Macro {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern.as_str() == other.pattern.as_str() && self.replacement == other.replacement
+    }
+}
+
 /// A collection of macros
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct MacroSystem {
     /// The macros defined in the system
     macros: Vec<Macro>,
@@ -31,7 +38,9 @@ impl MacroSystem {
     /// Add a macro to the system
     pub fn add_macro(&mut self, pattern: &str, replacement: &str) -> Result<(), Error> {
         let regex =
-            Regex::new(pattern).map_err(|_| Error::InvalidMacroPattern(pattern.to_string()))?;
+            Regex::new(pattern).map_err(|_| {
+            Error::InvalidMacroPattern(pattern.to_string(), SourceLocation::default())
+        })?;
 
         // Convert \1 to $1 for Rust regex compatibility
         let rust_replacement = Regex::new(r"\\(\d+)")
@@ -47,6 +56,34 @@ impl MacroSystem {
         Ok(())
     }
 
+    /// Get the number of macros in the system
+    pub fn len(&self) -> usize {
+        self.macros.len()
+    }
+
+    /// Check whether the system has no macros
+    pub fn is_empty(&self) -> bool {
+        self.macros.is_empty()
+    }
+
+    /// Get a system with the macros added after the first `count` ones
+    pub fn since(&self, count: usize) -> MacroSystem {
+        Self {
+            macros: self.macros[count.min(self.macros.len())..].to_vec(),
+        }
+    }
+
+    /// Merge another macro system into this one, applying its macros last.
+    /// Macros the system already has are skipped, so that merging the same
+    /// macros twice does not apply them twice.
+    pub fn merge(&mut self, other: MacroSystem) {
+        for mac in other.macros {
+            if !self.macros.contains(&mac) {
+                self.macros.push(mac);
+            }
+        }
+    }
+
     /// Apply macros to a string
     pub fn apply(&self, input: &str) -> String {
         let mut result = input.to_string();
@@ -62,11 +99,17 @@ impl MacroSystem {
     }
 }
 
-/// A collection of render rules
+/// The attributes of a style rule, as (name, value) pairs in order
+pub type StyleAttributes = Vec<(String, String)>;
+
+/// A collection of render rules, and of style rules for graphs
 #[derive(Debug, Default)]
 pub struct RenderSystem {
     /// The render rules defined in the system
     renders: HashMap<String, String>,
+
+    /// The style rules defined in the system
+    styles: HashMap<String, StyleAttributes>,
 }
 
 impl RenderSystem {
@@ -74,6 +117,7 @@ impl RenderSystem {
     pub fn new() -> Self {
         Self {
             renders: HashMap::new(),
+            styles: HashMap::new(),
         }
     }
 
@@ -92,9 +136,32 @@ impl RenderSystem {
         }
     }
 
+    /// Add a style rule to the system, its attributes overriding those of
+    /// the same name in a previous rule for the type
+    pub fn add_style(&mut self, type_name: &str, attributes: StyleAttributes) {
+        let style = self.styles.entry(type_name.to_string()).or_default();
+        for (name, value) in attributes {
+            style.retain(|(n, _)| *n != name);
+            style.push((name, value));
+        }
+    }
+
+    /// Get the style attributes of a type, if it has a style rule
+    pub fn style(&self, type_name: &str) -> Option<&StyleAttributes> {
+        self.styles.get(type_name)
+    }
+
+    /// Get the style attributes of all the types with a style rule
+    pub fn styles(&self) -> impl Iterator<Item = (&String, &StyleAttributes)> {
+        self.styles.iter()
+    }
+
     /// Merge another render system into this one
     pub fn merge(&mut self, other: RenderSystem) {
         self.renders.extend(other.renders);
+        for (type_name, attributes) in other.styles {
+            self.add_style(&type_name, attributes);
+        }
     }
 }
 
@@ -120,6 +187,26 @@ mod tests {
         assert_eq!(ms.apply("test5"), "result/5");
     }
 
+    #[test]
+    fn test_macro_system_since_and_merge() {
+        let mut ms = MacroSystem::new();
+        ms.add_macro("a", "b").unwrap();
+        ms.add_macro("b", "c").unwrap();
+
+        let tail = ms.since(1);
+        assert_eq!(tail.len(), 1);
+        assert_eq!(tail.apply("ab"), "ac");
+
+        let mut other = MacroSystem::new();
+        other.merge(tail);
+        other.merge(ms.since(5));
+        assert_eq!(other.len(), 1);
+
+        other.merge(ms.since(1));
+        assert_eq!(other.len(), 1);
+        assert_eq!(other.apply("ab"), "ac");
+    }
+
     #[test]
     fn test_render_system() {
         let mut rs = RenderSystem::new();
@@ -142,4 +229,21 @@ mod tests {
         assert_eq!(rs1.render("p", "test"), "P: test");
         assert_eq!(rs1.render("u", "test"), "U: test");
     }
+
+    #[test]
+    fn test_style_system_merge() {
+        let attributes = |pairs: &[(&str, &str)]| pairs.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect();
+        let mut rs1 = RenderSystem::new();
+        rs1.add_style("p", attributes(&[("shape", "note"), ("color", "red")]));
+
+        let mut rs2 = RenderSystem::new();
+        rs2.add_style("p", attributes(&[("color", "blue")]));
+        rs2.add_style("u", attributes(&[("shape", "ellipse")]));
+
+        rs1.merge(rs2);
+
+        assert_eq!(rs1.style("p"), Some(&attributes(&[("shape", "note"), ("color", "blue")])));
+        assert_eq!(rs1.style("u"), Some(&attributes(&[("shape", "ellipse")])));
+        assert_eq!(rs1.style("t"), None);
+    }
 }
@@ -1,113 +1,662 @@
 //! Command-line interface for the Oblique parser
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use notify::Watcher;
 use serde_json::json;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::io::{Stdout, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
 
-use oblique::Database;
+use oblique::{AutoIdStrategy, Clustering, Database, GraphOptions, ImportOptions, ObjectId, ResolveOptions};
 
-/// Command-line arguments
+/// Command-line interface
 #[derive(Parser, Debug)]
-#[clap(author, version, about)]
+#[clap(author, version, about, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    #[clap(flatten)]
+    args: Option<Args>,
+}
+
+/// Subcommands
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the output again each time one of the imported files changes
+    Watch {
+        #[clap(flatten)]
+        args: Args,
+
+        /// Poll the files for changes every this many milliseconds, instead
+        /// of using the native file notifications
+        #[clap(long, value_name = "MS")]
+        poll: Option<u64>,
+    },
+
+    /// Format files in place
+    Fmt {
+        /// Files to format
+        #[clap(name = "FILE", required = true)]
+        files: Vec<PathBuf>,
+
+        /// Only list the files that are not formatted, and fail if there are any
+        #[clap(long)]
+        check: bool,
+
+        /// Number of spaces per level of indentation
+        #[clap(long, value_name = "N", default_value = "2")]
+        indent: usize,
+    },
+
+    /// Rename an object, e.g. p/old p/new, or a type with --type, in all the
+    /// files imported from a file
+    Rename {
+        /// The object or type to rename
+        from: String,
+
+        /// Its new name
+        to: String,
+
+        /// File importing all the files to edit
+        #[clap(name = "FILE")]
+        input_file: PathBuf,
+
+        /// Rename a type instead of an object
+        #[clap(long = "type")]
+        rename_type: bool,
+
+        /// Only list the files that would change
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Directory to search for imported files, before those in OBLIQUE_PATH
+        #[clap(short = 'I', long = "include", value_name = "DIR")]
+        include_paths: Vec<PathBuf>,
+    },
+
+    /// Write hashes of their contents as the identifiers of the objects
+    /// defined with "type/" in all the files imported from a file
+    WriteIds {
+        /// File importing all the files to edit
+        #[clap(name = "FILE")]
+        input_file: PathBuf,
+
+        /// Only list the files that would change
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Directory to search for imported files, before those in OBLIQUE_PATH
+        #[clap(short = 'I', long = "include", value_name = "DIR")]
+        include_paths: Vec<PathBuf>,
+    },
+}
+
+/// Command-line arguments
+#[derive(clap::Args, Debug)]
 struct Args {
-    /// Input file to parse
+    /// Input file to parse, or a database saved with --format proto or
+    /// textproto (.pb, .binpb, .textproto, .txtpb or .pbtxt) to load
     #[clap(name = "FILE")]
     input_file: PathBuf,
 
-    /// Output format (json, text, dot)
+    /// Output format (json, text, dot, tree, html, mermaid, graphml, cypher,
+    /// proto, textproto)
     #[clap(short, long, default_value = "text")]
     format: String,
 
-    /// Query string
+    /// Directory to write the site to, for the html format
+    #[clap(short, long, value_name = "DIR")]
+    output: Option<PathBuf>,
+
+    /// File of /style rules for graph formats, overriding those of the input
+    #[clap(long, value_name = "FILE")]
+    dot_style: Option<PathBuf>,
+
+    /// Direction of the layout of graph formats (LR, RL, TB, BT)
+    #[clap(long, value_name = "DIR", default_value = "LR", value_parser = ["LR", "RL", "TB", "BT"])]
+    rankdir: String,
+
+    /// Number of characters of the contents shown in the nodes of graph formats
+    #[clap(long, value_name = "N", default_value = "40")]
+    label_length: usize,
+
+    /// Group the nodes of graph formats into clusters (none, type, parent)
+    #[clap(long, value_name = "BY", default_value = "none", value_parser = ["none", "type", "parent"])]
+    cluster: String,
+
+    /// Draw only the objects around this one in graph formats, e.g. p/alpha
+    #[clap(long, value_name = "REF")]
+    around: Option<String>,
+
+    /// Number of links to follow from the object given with --around
+    #[clap(long, value_name = "N", default_value = "1")]
+    hops: usize,
+
+    /// Report references to undefined objects of strict types as errors (default)
+    #[clap(long, conflicts_with = "lenient")]
+    strict: bool,
+
+    /// Leave references to undefined objects of strict types unresolved
+    #[clap(long)]
+    lenient: bool,
+
+    /// Directory to search for imported files, before those in OBLIQUE_PATH
+    #[clap(short = 'I', long = "include", value_name = "DIR")]
+    include_paths: Vec<PathBuf>,
+
+    /// Parse files each time they are imported, instead of only once
+    #[clap(long)]
+    include_many: bool,
+
+    /// Query string, e.g. "select t where p/alpha order by ident"; graph
+    /// formats draw only the objects found
     #[clap(short, long)]
     query: Option<String>,
+
+    /// How to generate the identifiers of objects defined without one
+    /// (sequential, hash)
+    #[clap(long, value_name = "STRATEGY", default_value = "sequential", value_parser = ["sequential", "hash"])]
+    auto_ids: String,
 }
 
 fn main() {
-    let args = Args::parse();
+    let cli = Cli::parse();
+    match (cli.command, cli.args) {
+        (Some(Command::Watch { args, poll }), _) => watch(&args, poll),
+        (Some(Command::Fmt { files, check, indent }), _) => {
+            if !format_files(&files, check, indent) {
+                std::process::exit(1);
+            }
+        }
+        (Some(Command::Rename { from, to, input_file, rename_type, dry_run, include_paths }), _) => {
+            let target = match parse_rename_target(&from, &to, rename_type) {
+                Ok(target) => target,
+                Err(message) => {
+                    eprintln!("Error: {}", message);
+                    std::process::exit(2);
+                }
+            };
+            let db = load_for_edit(&input_file, &include_paths, AutoIdStrategy::Sequential);
+            if !db.is_some_and(|db| edit_files(oblique::rename(&db, &target), dry_run)) {
+                std::process::exit(1);
+            }
+        }
+        (Some(Command::WriteIds { input_file, dry_run, include_paths }), _) => {
+            let db = load_for_edit(&input_file, &include_paths, AutoIdStrategy::ContentHash);
+            if !db.is_some_and(|db| edit_files(oblique::write_auto_ids(&db), dry_run)) {
+                std::process::exit(1);
+            }
+        }
+        (None, Some(args)) => {
+            let mut db = create_database(&args);
+            let result = if oblique::proto::is_proto_file(&args.input_file) {
+                Database::load_proto(&args.input_file).map(|loaded| db = loaded)
+            } else {
+                db.import_file(&args.input_file)
+            };
+            if !print_output(&db, result, &args) {
+                std::process::exit(1);
+            }
+        }
+        (None, None) => unreachable!("clap requires the arguments without a subcommand"),
+    }
+}
 
-    // Create a new database
+/// Create a database configured from the arguments, reporting every error
+/// rather than the first
+fn create_database(args: &Args) -> Database {
     let mut db = Database::new();
+    db.set_collect_diagnostics(true);
+    db.set_resolve_options(ResolveOptions {
+        strict: args.strict || !args.lenient,
+        ..ResolveOptions::default()
+    });
+    let mut import_options = ImportOptions::from_env();
+    import_options.include_paths.splice(0..0, args.include_paths.iter().cloned());
+    import_options.include_many = args.include_many;
+    db.set_import_options(import_options);
+    db.set_auto_id_strategy(match args.auto_ids.as_str() {
+        "hash" => AutoIdStrategy::ContentHash,
+        _ => AutoIdStrategy::Sequential,
+    });
+    db
+}
 
-    // Import the file
-    match db.import_file(&args.input_file) {
-        Ok(()) => {
-            if let Some(query) = args.query {
-                // TODO: Implement proper query parsing
-                // For now, simple "list items of type X"
-                // format: "type:X"
-                if query.starts_with("type:") {
-                    let type_name = &query[5..];
-                    println!("Objects of type '{}':", type_name);
-                    for (id, obj) in &db.objects {
-                        if id.type_name == type_name {
-                             let rendered_id = db.render_system.render(&id.type_name, id.ident.as_deref().unwrap_or(""));
-                             println!("  {} {}", rendered_id, obj.contents);
-                        }
-                    }
-                } else {
-                    eprintln!("Unsupported query format. Use 'type:<typename>'");
+/// Print the errors of an import or the database in the chosen format, and
+/// return whether there were no errors
+fn print_output(db: &Database, result: Result<(), oblique::Error>, args: &Args) -> bool {
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        return false;
+    }
+    if !db.diagnostics.is_empty() {
+        for diagnostic in &db.diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        eprintln!("{} error(s)", db.diagnostics.len());
+        return false;
+    }
+
+    // The query of graph formats selects the objects of the graph
+    let generate_graph = match args.format.as_str() {
+        "dot" => Some(oblique::generate_dot::<Stdout> as GraphGenerator),
+        "mermaid" => Some(oblique::generate_mermaid::<Stdout> as GraphGenerator),
+        "graphml" => Some(oblique::generate_graphml::<Stdout> as GraphGenerator),
+        "cypher" => Some(oblique::generate_cypher::<Stdout> as GraphGenerator),
+        _ => None,
+    };
+    if let Some(generate_graph) = generate_graph {
+        return match graph_options(db, args) {
+            Ok(options) => {
+                generate_graph(db, &options, std::io::stdout()).unwrap();
+                true
+            }
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                false
+            }
+        };
+    }
+
+    match &args.query {
+        Some(query) => match oblique::run_query(db, query) {
+            Ok(result) => {
+                print_query_result(db, &result, &args.format);
+                true
+            }
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                false
+            }
+        },
+        None if args.format == "html" => match &args.output {
+            Some(dir) => match oblique::generate_html(db, dir) {
+                Ok(()) => true,
+                Err(err) => {
+                    eprintln!("Error: cannot write {}: {}", dir.display(), err);
+                    false
                 }
-                return;
+            },
+            None => {
+                eprintln!("Error: the html format requires an output directory (--output DIR)");
+                false
             }
+        },
+        None => {
+            print_database(db, &args.format);
+            true
+        }
+    }
+}
+
+/// A function writing the graph in some format
+type GraphGenerator = fn(&Database, &GraphOptions, Stdout) -> std::io::Result<()>;
+
+/// Get the options of graph formats from the arguments, drawing only the
+/// objects found by the query and those around the given object, if any
+fn graph_options(db: &Database, args: &Args) -> Result<GraphOptions, String> {
+    let mut options = GraphOptions {
+        max_label_len: args.label_length,
+        rankdir: args.rankdir.clone(),
+        clustering: match args.cluster.as_str() {
+            "type" => Clustering::Type,
+            "parent" => Clustering::Parent,
+            _ => Clustering::None,
+        },
+        ..GraphOptions::default()
+    };
+    if let Some(path) = &args.dot_style {
+        options.load_styles(path).map_err(|err| err.to_string())?;
+    }
+    if let Some(query) = &args.query {
+        let result = oblique::run_query(db, query).map_err(|err| err.to_string())?;
+        options.objects = Some(result.objects().map(|o| o.id.clone()).collect());
+    }
+    if let Some(around) = &args.around {
+        let (type_name, ident) = around
+            .split_once('/')
+            .ok_or_else(|| format!("'{}' is not a reference like type/ident", around))?;
+        let id = ObjectId { type_name: type_name.to_string(), ident: Some(ident.to_string()) };
+        if !db.objects.contains_key(&id) {
+            return Err(format!("no object '{}'", around));
+        }
+        let neighborhood = db.neighborhood(&id, args.hops);
+        options.objects = Some(match options.objects {
+            Some(objects) => objects.intersection(&neighborhood).cloned().collect(),
+            None => neighborhood,
+        });
+    }
+    Ok(options)
+}
+
+/// Print the objects found by a query
+fn print_query_result(db: &Database, result: &oblique::QueryResult, format: &str) {
+    if format == "json" {
+        println!("{}", serde_json::to_string_pretty(&result).unwrap());
+        return;
+    }
+
+    for group in &result.groups {
+        let indent = if result.groups.len() > 1 || group.key.is_some() {
+            println!("{}:", group.key.as_deref().unwrap_or("(none)"));
+            "  "
+        } else {
+            ""
+        };
+        for obj in &group.objects {
+            let rendered_id = db.render_system.render(&obj.id.type_name, obj.id.ident.as_deref().unwrap_or(""));
+            println!("{}{} {}", indent, rendered_id, obj.contents);
+        }
+    }
+}
 
-            // Output the database
-            match args.format.as_str() {
-                "json" => {
-                    let output = json!({
-                        "types": db.types,
-                        "objects": db.objects,
-                    });
-                    println!("{}", serde_json::to_string_pretty(&output).unwrap());
+/// Print the whole database
+fn print_database(db: &Database, format: &str) {
+    match format {
+        "json" => {
+            // Object IDs are not strings, so objects are emitted as a list
+            let mut objects: Vec<_> = db.objects.values().collect();
+            objects.sort_by(|a, b| a.id.cmp(&b.id));
+            let output = json!({
+                "types": db.types,
+                "objects": objects,
+            });
+            println!("{}", serde_json::to_string_pretty(&output).unwrap());
+        }
+        "proto" => {
+            let bytes = oblique::proto::encode(db);
+            std::io::stdout().write_all(&bytes).unwrap();
+        }
+        "textproto" => print!("{}", oblique::proto::to_text(db)),
+        "tree" => {
+            let mut printed = HashSet::new();
+            for id in db.top_level_objects() {
+                print_tree(db, id, 0, &mut printed);
+            }
+        }
+        _ => {
+            println!("Types:");
+            for (name, typ) in &db.types {
+                println!(
+                    "  {}: {} ({}{})",
+                    name,
+                    typ.contents,
+                    match typ.flavor {
+                        oblique::TypeFlavor::Strict => "strict",
+                        oblique::TypeFlavor::Lazy => "lazy",
+                        oblique::TypeFlavor::Ignore => "ignore",
+                    },
+                    typ.location.as_ref().map(|l| format!(", {}", l)).unwrap_or_default()
+                );
+            }
+
+            println!("\nObjects:");
+            for (id, obj) in &db.objects {
+                let rendered_id = db.render_system.render(&id.type_name, id.ident.as_deref().unwrap_or(""));
+                println!(
+                    "  {}: {}",
+                    rendered_id,
+                    obj.contents
+                );
+
+                if let Some(location) = &obj.location {
+                    println!("    Location: {}", location);
                 }
-                "dot" => {
-                    oblique::generate_dot(&db, std::io::stdout()).unwrap();
+
+                if obj.origin != oblique::ObjectOrigin::Explicit {
+                    println!("    Origin: {}", obj.origin);
                 }
-                _ => {
-                    println!("Types:");
-                    for (name, typ) in &db.types {
-                        println!(
-                            "  {}: {} ({})",
-                            name,
-                            typ.contents,
-                            match typ.flavor {
-                                oblique::TypeFlavor::Strict => "strict",
-                                oblique::TypeFlavor::Lazy => "lazy",
-                                oblique::TypeFlavor::Ignore => "ignore",
-                            }
-                        );
+
+                if !obj.refs.is_empty() {
+                    println!("    References:");
+                    for reference in &obj.refs {
+                        let rendered_ref = db.render_system.render(&reference.type_name, &reference.ident);
+                        println!("      {}{}", rendered_ref, edge_label(reference));
                     }
+                }
 
-                    println!("\nObjects:");
-                    for (id, obj) in &db.objects {
-                        let rendered_id = db.render_system.render(&id.type_name, id.ident.as_deref().unwrap_or(""));
-                        println!(
-                            "  {}: {}",
-                            rendered_id,
-                            obj.contents
-                        );
-
-                        if !obj.refs.is_empty() {
-                            println!("    References:");
-                            for reference in &obj.refs {
-                                let rendered_ref = db.render_system.render(&reference.type_name, &reference.ident);
-                                println!("      {}", rendered_ref);
-                            }
-                        }
+                let referrers: Vec<_> = db.referrers(id).collect();
+                if !referrers.is_empty() {
+                    println!("    Referenced By:");
+                    for referrer in referrers {
+                        let rendered_ref = db.render_system.render(&referrer.type_name, referrer.ident.as_deref().unwrap_or(""));
+                        println!("      {}", rendered_ref);
+                    }
+                }
 
-                        if !obj.unresolved_refs.is_empty() {
-                            println!("    Unresolved References:");
-                            for reference in &obj.unresolved_refs {
-                                println!("      {}/{}", reference.type_name, reference.ident);
-                            }
-                        }
+                if !obj.unresolved_refs.is_empty() {
+                    println!("    Unresolved References:");
+                    for reference in &obj.unresolved_refs {
+                        println!("      {}/{}{}", reference.type_name, reference.ident, edge_label(reference));
                     }
                 }
             }
         }
+    }
+}
+
+/// Print an object and the objects under it, indented by depth
+fn print_tree<'a>(db: &'a Database, id: &'a oblique::ObjectId, depth: usize, printed: &mut HashSet<&'a oblique::ObjectId>) {
+    // Parent links cannot form cycles from indentation alone, but an object
+    // is only printed once anyway
+    if !printed.insert(id) {
+        return;
+    }
+    let rendered_id = db.render_system.render(&id.type_name, id.ident.as_deref().unwrap_or(""));
+    let contents = db.objects.get(id).map_or("", |o| o.contents.as_str());
+    println!("{}{} {}", "  ".repeat(depth), rendered_id, contents);
+    for child in db.children_of(id) {
+        print_tree(db, child, depth + 1, printed);
+    }
+}
+
+/// Describe how an object refers to another, when it is not a single mention
+fn edge_label(reference: &oblique::Reference) -> String {
+    let mut label = String::new();
+    if reference.kind != oblique::EdgeKind::Mention {
+        label.push_str(&format!(" ({})", reference.kind));
+    }
+    if reference.count > 1 {
+        label.push_str(&format!(" x{}", reference.count));
+    }
+    label
+}
+
+/// Format files in place, or list those that are not formatted when checking,
+/// and return whether all of them were formatted
+fn format_files(files: &[PathBuf], check: bool, indent: usize) -> bool {
+    let options = oblique::FormatOptions { indent_width: indent };
+    let mut ok = true;
+    for path in files {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Error: {}: {}", path.display(), err);
+                ok = false;
+                continue;
+            }
+        };
+        let formatted = oblique::format_source(&source, &options);
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{}", path.display());
+            ok = false;
+        } else if let Err(err) = std::fs::write(path, formatted) {
+            eprintln!("Error: {}: {}", path.display(), err);
+            ok = false;
+        }
+    }
+    ok
+}
+
+/// Get what to rename from the command line, "t tsk" for a type and
+/// "p/old p/new" or "p/old new" for an object
+fn parse_rename_target(from: &str, to: &str, rename_type: bool) -> Result<oblique::RenameTarget, String> {
+    if rename_type {
+        return Ok(oblique::RenameTarget::Type(from.to_string(), to.to_string()));
+    }
+    let (type_name, ident) = from
+        .split_once('/')
+        .ok_or_else(|| format!("'{}' is not a reference like type/ident", from))?;
+    let new_ident = match to.split_once('/') {
+        Some((new_type, new_ident)) if new_type == type_name => new_ident,
+        Some(_) => return Err(format!("cannot change the type of '{}'; rename the type with --type", from)),
+        None => to,
+    };
+    let reference = oblique::Reference::new(type_name, ident, oblique::EdgeKind::Mention);
+    Ok(oblique::RenameTarget::Object(reference, new_ident.to_string()))
+}
+
+/// Import a file to edit it and the files it imports, or print the errors
+/// and return `None` if it has any, since editing files that do not parse
+/// could miss references
+fn load_for_edit(input_file: &Path, include_paths: &[PathBuf], strategy: AutoIdStrategy) -> Option<Database> {
+    let mut db = Database::new();
+    db.set_collect_diagnostics(true);
+    db.set_resolve_options(ResolveOptions { strict: false, ..ResolveOptions::default() });
+    db.set_auto_id_strategy(strategy);
+    let mut import_options = ImportOptions::from_env();
+    import_options.include_paths.splice(0..0, include_paths.iter().cloned());
+    db.set_import_options(import_options);
+
+    let result = db.import_file(input_file);
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        return None;
+    }
+    if !db.diagnostics.is_empty() {
+        for diagnostic in &db.diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        eprintln!("{} error(s)", db.diagnostics.len());
+        return None;
+    }
+    Some(db)
+}
+
+/// Apply the edits of a rename, or only list the files they change, and
+/// return whether it succeeded
+fn edit_files(rename: Result<oblique::Rename, oblique::Error>, dry_run: bool) -> bool {
+    let rename = match rename {
+        Ok(rename) => rename,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return false;
+        }
+    };
+    for warning in &rename.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    for (path, edits) in &rename.edits {
+        println!("{}: {} change(s)", path.display(), edits.len());
+    }
+    if dry_run {
+        return true;
+    }
+    match rename.apply() {
+        Ok(()) => true,
         Err(err) => {
             eprintln!("Error: {}", err);
-            std::process::exit(1);
+            false
         }
     }
 }
+
+/// Import the file and print the output, then parse the files that change
+/// again and print the output again, until interrupted
+fn watch(args: &Args, poll: Option<u64>) {
+    let mut db = create_database(args);
+    let result = db.import_file(&args.input_file);
+    print_output(&db, result, args);
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher: Box<dyn Watcher> = match poll {
+        Some(ms) => Box::new(poll_watcher(tx, ms)),
+        None => match notify::recommended_watcher(tx.clone()) {
+            Ok(watcher) => Box::new(watcher),
+            Err(err) => {
+                // E.g. when out of inotify watches
+                eprintln!("Warning: {}, polling for changes instead", err);
+                Box::new(poll_watcher(tx, 500))
+            }
+        },
+    };
+
+    // Files are often replaced rather than written to when saved, so their
+    // directories are watched instead of the files themselves
+    let mut watched = HashSet::new();
+    loop {
+        let files = watched_files(&db, args);
+        for file in &files {
+            if let Some(dir) = file.parent() {
+                if watched.insert(dir.to_path_buf()) {
+                    if let Err(err) = watcher.watch(dir, notify::RecursiveMode::NonRecursive) {
+                        eprintln!("Warning: cannot watch {}: {}", dir.display(), err);
+                    }
+                }
+            }
+        }
+
+        // Wait for a change, then for the other changes of the same save
+        let mut changed = HashSet::new();
+        let mut timeout = None;
+        loop {
+            let event = match timeout {
+                None => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+                Some(timeout) => rx.recv_timeout(timeout),
+            };
+            match event {
+                Ok(Ok(event)) => {
+                    if event.kind.is_access() {
+                        continue;
+                    }
+                    for path in event.paths {
+                        let path = std::fs::canonicalize(&path).unwrap_or(path);
+                        if files.contains(&path) {
+                            changed.insert(path);
+                            timeout = Some(Duration::from_millis(100));
+                        }
+                    }
+                }
+                Ok(Err(err)) => eprintln!("Warning: {}", err),
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        let mut changed: Vec<_> = changed.into_iter().collect();
+        changed.sort();
+        let mut result = Ok(());
+        for path in &changed {
+            eprintln!("Reloading {}", path.display());
+            result = db.reload_file(path);
+            if result.is_err() {
+                break;
+            }
+        }
+        print_output(&db, result, args);
+    }
+}
+
+/// Create a watcher polling for changes
+fn poll_watcher(tx: mpsc::Sender<notify::Result<notify::Event>>, ms: u64) -> notify::PollWatcher {
+    let config = notify::Config::default()
+        .with_poll_interval(Duration::from_millis(ms))
+        .with_compare_contents(true);
+    notify::PollWatcher::new(tx, config).expect("Failed to create a polling watcher")
+}
+
+/// Get the canonical paths of the files to watch
+fn watched_files(db: &Database, args: &Args) -> HashSet<PathBuf> {
+    // The input file is not known to the database if it could not be read
+    db.source_files()
+        .into_iter()
+        .chain(std::iter::once(args.input_file.as_path()))
+        .filter_map(|p: &Path| std::fs::canonicalize(p).ok())
+        .collect()
+}
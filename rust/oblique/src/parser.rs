@@ -1,21 +1,114 @@
 //! Parser for the Oblique language
 
-use std::collections::{HashSet};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::fs;
 
-use crate::ast::{Type, TypeFlavor, Object, ObjectId, Reference};
-use crate::error::Error;
-use crate::lexer::{tokenize_line, Token};
-use crate::macros::{MacroSystem, RenderSystem};
+use crate::ast::{EdgeKind, Type, TypeFlavor, Object, ObjectId, ObjectOrigin, Reference, ReferenceOccurrence, SourceLocation, Span};
+use crate::cst::{Cst, CstLine};
+use crate::error::{Diagnostic, Error};
+use crate::lexer::{lex_line, SpannedToken, Token};
+use crate::macros::{MacroSystem, RenderSystem, StyleAttributes};
+
+/// Options controlling how `/import` statements are handled
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportOptions {
+    /// Parse a file again each time it is imported, instead of only once
+    pub include_many: bool,
+
+    /// Directories searched for imported files, in order, after the directory
+    /// of the importing file
+    pub include_paths: Vec<PathBuf>,
+}
+
+impl ImportOptions {
+    /// Create options whose include paths are taken from the `OBLIQUE_PATH`
+    /// environment variable, a list of directories like `PATH`
+    pub fn from_env() -> Self {
+        let include_paths = std::env::var_os("OBLIQUE_PATH")
+            .map(|value| std::env::split_paths(&value).filter(|p| !p.as_os_str().is_empty()).collect())
+            .unwrap_or_default();
+        Self {
+            include_paths,
+            ..Self::default()
+        }
+    }
+}
+
+/// The macros and types visible to the statements of a file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Scope {
+    macros: MacroSystem,
+    types: HashSet<String>,
+}
+
+impl Scope {
+    /// Get the macros and types added to this scope since it was `base`
+    fn since(&self, base: &Scope) -> Scope {
+        Scope {
+            macros: self.macros.since(base.macros.len()),
+            types: self.types.difference(&base.types).cloned().collect(),
+        }
+    }
+
+    /// Add the macros and types of another scope to this one
+    fn merge(&mut self, other: Scope) {
+        self.macros.merge(other.macros);
+        self.types.extend(other.types);
+    }
+}
+
+/// What a file was parsed with and what it produced besides its objects, to
+/// parse it again on its own when it changes
+#[derive(Debug, Clone)]
+pub(crate) struct FileContext {
+    /// The path of the file, as used in source locations
+    pub path: PathBuf,
+    /// The parent inherited from the `/import` statement
+    pub parent: Option<Reference>,
+    /// The macros and types visible at the start of the file
+    pub scope: Scope,
+    /// The names of the types declared before the file
+    pub declared_types: HashSet<String>,
+    /// The canonical paths of the files imported by the file, in order
+    pub imports: Vec<PathBuf>,
+    /// The macros and types declared by the file itself, exported to the
+    /// importing file by `/import --vocab`
+    pub exports: Scope,
+}
 
 /// A stateful parser for the Oblique language
 pub struct Parser {
     pub types: Vec<Type>,
     pub objects: Vec<Object>,
-    pub macro_system: MacroSystem,
     pub render_system: RenderSystem,
-    search_paths: Vec<PathBuf>,
+    /// Errors collected instead of aborting, in diagnostics mode
+    pub diagnostics: Vec<Diagnostic>,
+    collect_diagnostics: bool,
+    current_file: Option<PathBuf>,
+    import_options: ImportOptions,
+    /// Canonical paths of the files being parsed, outermost first
+    import_stack: Vec<PathBuf>,
+    /// Canonical paths of all the files parsed so far
+    imported: HashSet<PathBuf>,
+    /// The parent of the `/import` statement being processed, which becomes
+    /// the parent of the top-level objects of the imported file
+    inherited_parent: Option<Reference>,
+    /// The macros and types visible in the file being parsed
+    scope: Scope,
+    /// The context of each parsed file, by canonical path
+    pub(crate) files: HashMap<PathBuf, FileContext>,
+    /// The names of the types declared in any file
+    declared_types: HashSet<String>,
+    /// Contents to parse instead of those on disk, by canonical path
+    unsaved_files: HashMap<PathBuf, String>,
+    /// The spans in the file of the references of the line being parsed that
+    /// are in its text, by their offset in the line after macro expansion
+    literal_spans: HashMap<usize, Span>,
+    /// The line being parsed as written, before macro expansion
+    line_text: String,
+    /// The offset of the line being parsed in its file
+    line_start: usize,
 }
 
 impl Parser {
@@ -24,51 +117,145 @@ impl Parser {
         let mut parser = Self {
             types: Vec::new(),
             objects: Vec::new(),
-            macro_system: MacroSystem::new(),
             render_system: RenderSystem::new(),
-            search_paths: Vec::new(),
+            diagnostics: Vec::new(),
+            collect_diagnostics: false,
+            current_file: None,
+            import_options: ImportOptions::default(),
+            import_stack: Vec::new(),
+            imported: HashSet::new(),
+            inherited_parent: None,
+            scope: Scope::default(),
+            files: HashMap::new(),
+            declared_types: HashSet::new(),
+            unsaved_files: HashMap::new(),
+            literal_spans: HashMap::new(),
+            line_text: String::new(),
+            line_start: 0,
         };
 
-        // Add the default item type
+        // Add the default item type, which is visible everywhere
         parser.types.push(Type {
             name: "item".to_string(),
             contents: "Item type".to_string(),
             flavor: TypeFlavor::Lazy,
-            lineno: None,
+            location: None,
         });
+        parser.scope.types.insert("item".to_string());
+        parser.declared_types.insert("item".to_string());
 
         parser
     }
 
-    /// Add a search path for imports
-    pub fn add_search_path<P: AsRef<Path>>(&mut self, path: P) {
-        self.search_paths.push(path.as_ref().to_path_buf());
+    /// Collect errors into `diagnostics` and keep going instead of stopping at
+    /// the first one
+    pub fn set_collect_diagnostics(&mut self, collect: bool) {
+        self.collect_diagnostics = collect;
+    }
+
+    /// Set the options used when handling imports
+    pub fn set_import_options(&mut self, options: ImportOptions) {
+        self.import_options = options;
+    }
+
+    /// Set contents to parse instead of those of the files on disk, by
+    /// canonical path, e.g. the unsaved buffers of an editor
+    pub fn set_unsaved_files(&mut self, files: HashMap<PathBuf, String>) {
+        self.unsaved_files = files;
+    }
+
+    /// Create a parser for parsing a previously parsed file again on its own,
+    /// in the same context as before. The files it imports are not parsed
+    /// again, but their vocabulary is still imported.
+    pub(crate) fn for_file(files: &HashMap<PathBuf, FileContext>, canonical: &Path) -> Option<Self> {
+        let context = files.get(canonical)?;
+        let mut parser = Self::new();
+        parser.imported = files.keys().filter(|p| *p != canonical).cloned().collect();
+        parser.files = files.clone();
+        parser.inherited_parent = context.parent.clone();
+        parser.scope = context.scope.clone();
+        parser.declared_types = context.declared_types.clone();
+        Some(parser)
     }
 
     /// Parse a file
     pub fn parse_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
         let path = path.as_ref();
-        let content = fs::read_to_string(path)
-            .map_err(|e| Error::Io(e))?;
+        // Unsaved files may not exist on disk yet
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let content = match self.unsaved_files.get(&canonical) {
+            Some(content) => content.clone(),
+            None => fs::read_to_string(path).map_err(Error::Io)?,
+        };
 
-        // Add the file's directory to search paths for relative imports
-        if let Some(parent) = path.parent() {
-            self.add_search_path(parent);
+        let entry_scope = self.scope.clone();
+        self.files.insert(canonical.clone(), FileContext {
+            path: path.to_path_buf(),
+            parent: self.inherited_parent.clone(),
+            scope: entry_scope.clone(),
+            declared_types: self.declared_types.clone(),
+            imports: Vec::new(),
+            exports: Scope::default(),
+        });
+
+        self.imported.insert(canonical.clone());
+        self.import_stack.push(canonical.clone());
+        let previous_file = self.current_file.replace(path.to_path_buf());
+        let result = self.parse_string(&content);
+        self.current_file = previous_file;
+        self.import_stack.pop();
+
+        let exports = self.scope.since(&entry_scope);
+        if let Some(context) = self.files.get_mut(&canonical) {
+            context.exports = exports;
         }
+        result
+    }
 
-        self.parse_string(&content)
+    /// The location of a byte offset in the line being parsed as written,
+    /// since macros change the length of the line after expansion
+    fn location(&self, line_idx: usize, offset: usize) -> SourceLocation {
+        SourceLocation {
+            file: self.current_file.clone(),
+            line: line_idx,
+            column: self.line_text[..offset].chars().count() + 1,
+        }
     }
 
     fn process_object(
         &mut self,
         type_name: String,
         ident: Option<String>,
-        content_tokens: &[Token],
-        line_idx: usize,
+        content_tokens: &[SpannedToken],
+        line: &str,
+        location: SourceLocation,
         parent_ref: Option<Reference>,
-    ) -> Object {
-        let contents = self.join_tokens(content_tokens);
+    ) -> Result<Object, Error> {
+        let contents = slice_contents(line, content_tokens);
         let (refs, unresolved_refs) = self.extract_references(content_tokens);
+        let occurrences: Vec<ReferenceOccurrence> = content_tokens
+            .iter()
+            .filter_map(|t| match &t.token {
+                Token::Reference { type_name, ident } => {
+                    let span = self.literal_spans.get(&t.span.start).copied();
+                    Some(ReferenceOccurrence {
+                        reference: Reference::new(type_name, ident, mention_kind(span)),
+                        // Those produced by macros are located at the object
+                        location: match span {
+                            Some(span) => self.location(location.line, span.start - self.line_start),
+                            None => location.clone(),
+                        },
+                        span,
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+
+        self.check_in_scope(&type_name, &location)?;
+        for occurrence in &occurrences {
+            self.check_in_scope(&occurrence.reference.type_name, &occurrence.location)?;
+        }
 
         let mut obj = Object {
             id: ObjectId {
@@ -78,40 +265,44 @@ impl Parser {
             contents,
             refs,
             unresolved_refs,
-            lineno: Some(line_idx),
+            occurrences,
+            location: Some(location),
+            origin: ObjectOrigin::Explicit,
         };
 
         if let Some(p) = parent_ref {
             obj.unresolved_refs.insert(p);
         }
 
-        obj
+        Ok(obj)
+    }
+
+    /// Check that a type declared in some file is visible in the current one.
+    /// Types that are not declared anywhere yet are left to the resolution.
+    fn check_in_scope(&self, type_name: &str, location: &SourceLocation) -> Result<(), Error> {
+        if self.declared_types.contains(type_name) && !self.scope.types.contains(type_name) {
+            return Err(Error::TypeNotInScope(type_name.to_string(), location.clone()));
+        }
+        Ok(())
     }
 
     /// Parse a string
     pub fn parse_string(&mut self, content: &str) -> Result<(), Error> {
-        let lines: Vec<&str> = content.lines().collect();
-        let mut line_idx = 0;
+        let cst = Cst::parse(content);
         
         // Stack of (indentation_level, parent_reference)
         let mut context_stack: Vec<(usize, Reference)> = Vec::new();
 
-        while line_idx < lines.len() {
-            let original_line = lines[line_idx];
-            line_idx += 1;
+        for source in &cst.lines {
+            let original_line = cst.line_text(source);
+            let line_idx = source.number;
 
-            // Calculate indentation
-            let trimmed = original_line.trim_start();
-            if trimmed.is_empty() {
+            // Skip blank lines and comments (ignore for indentation purposes)
+            if source.is_blank() || source.is_comment() {
                 continue;
             }
             
-            // Skip comments (ignore for indentation purposes)
-            if trimmed.starts_with('#') {
-                continue;
-            }
-            
-            let indent = original_line.len() - trimmed.len();
+            let indent = source.indent.len();
             
             // Adjust stack based on indentation
             while !context_stack.is_empty() {
@@ -123,190 +314,344 @@ impl Parser {
             }
             
             // Capture parent reference before processing the line
-            let parent_ref = context_stack.last().map(|(_, r)| r.clone());
+            let parent_ref = context_stack
+                .last()
+                .map(|(_, r)| r.clone())
+                .or_else(|| self.inherited_parent.clone());
 
             // Apply macros
-            let line = self.macro_system.apply(original_line);
+            let line = self.scope.macros.apply(original_line);
             
-            let tokens = tokenize_line(&line);
+            let tokens = lex_line(&line);
             if tokens.is_empty() {
                 continue;
             }
+            self.literal_spans = literal_spans(&tokens, source);
+            self.line_text = original_line.to_string();
+            self.line_start = source.span.start;
 
-            match &tokens[0] {
-                Token::TypeDecl(name) | Token::LazyTypeDecl(name) | Token::IgnoreTypeDecl(name) => {
-                    let contents = self.join_tokens(&tokens[1..tokens.len()-1]);
-                    let flavor = match &tokens[0] {
-                        Token::TypeDecl(_) => TypeFlavor::Strict,
-                        Token::LazyTypeDecl(_) => TypeFlavor::Lazy,
-                        Token::IgnoreTypeDecl(_) => TypeFlavor::Ignore,
-                        _ => unreachable!(),
-                    };
-                    
-                    self.types.push(Type {
-                        name: name.clone(),
-                        contents,
-                        flavor,
-                        lineno: Some(line_idx),
-                    });
-                },
-                Token::MacroDecl => {
-                    // Parse macro from the raw line to preserve spaces and slashes in replacement
-                    let trimmed = line.trim();
-                    // Skip "/macro"
-                    if let Some(rest) = trimmed.strip_prefix("/macro") {
-                        let rest = rest.trim_start();
-                        // Find end of pattern (first whitespace)
-                        if let Some(idx) = rest.find(char::is_whitespace) {
-                            let pattern = &rest[..idx];
-                            let replacement = rest[idx..].trim_start();
-                            
-                            self.macro_system.add_macro(pattern, replacement)?;
-                        } else {
-                             return Err(Error::Parse {
-                                line: line_idx,
-                                message: "Invalid macro declaration: missing replacement".to_string(),
-                            });
-                        }
-                    }
-                },
-                Token::RenderDecl => {
-                    // Parse render from the raw line
-                    let trimmed = line.trim();
-                     // Skip "/render"
-                    if let Some(rest) = trimmed.strip_prefix("/render") {
-                        let rest = rest.trim_start();
-                        // Find end of type (first whitespace)
-                        if let Some(idx) = rest.find(char::is_whitespace) {
-                            let type_name = &rest[..idx];
-                            let template = rest[idx..].trim_start(); // Keep the rest of the line as template
-                            
-                            self.render_system.add_render(type_name, template);
-                        } else {
-                             return Err(Error::Parse {
-                                line: line_idx,
-                                message: "Invalid render declaration: missing template".to_string(),
-                            });
-                        }
+            match self.parse_statement(&tokens, &line, line_idx, parent_ref) {
+                Ok(Some(reference)) => context_stack.push((indent, reference)),
+                Ok(None) => {}
+                Err(err) => self.report(err)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse a single tokenized statement, returning the reference of the
+    /// object it defines if any
+    fn parse_statement(
+        &mut self,
+        tokens: &[SpannedToken],
+        line: &str,
+        line_idx: usize,
+        parent_ref: Option<Reference>,
+    ) -> Result<Option<Reference>, Error> {
+        let location = self.location(line_idx, self.line_text.len() - self.line_text.trim_start().len());
+
+        match &tokens[0].token {
+            Token::TypeDecl(name) | Token::LazyTypeDecl(name) | Token::IgnoreTypeDecl(name) => {
+                let contents = slice_contents(line, &tokens[1..]);
+                let flavor = match &tokens[0].token {
+                    Token::TypeDecl(_) => TypeFlavor::Strict,
+                    Token::LazyTypeDecl(_) => TypeFlavor::Lazy,
+                    Token::IgnoreTypeDecl(_) => TypeFlavor::Ignore,
+                    _ => unreachable!(),
+                };
+                
+                self.scope.types.insert(name.clone());
+                self.declared_types.insert(name.clone());
+                self.types.push(Type {
+                    name: name.clone(),
+                    contents,
+                    flavor,
+                    location: Some(location),
+                });
+            },
+            Token::MacroDecl => {
+                // Parse macro from the raw line to preserve spaces and slashes in replacement
+                let trimmed = line.trim();
+                // Skip "/macro"
+                if let Some(rest) = trimmed.strip_prefix("/macro") {
+                    let rest = rest.trim_start();
+                    // Find end of pattern (first whitespace)
+                    if let Some(idx) = rest.find(char::is_whitespace) {
+                        let pattern = &rest[..idx];
+                        let replacement = rest[idx..].trim_start();
+                        
+                        self.scope
+                            .macros
+                            .add_macro(pattern, replacement)
+                            .map_err(|_| Error::InvalidMacroPattern(pattern.to_string(), location))?;
+                    } else {
+                         return Err(Error::Parse {
+                            location,
+                            message: "Invalid macro declaration: missing replacement".to_string(),
+                        });
                     }
-                },
-                Token::ImportDecl => {
-                    if tokens.len() < 2 {
-                        return Err(Error::Parse {
-                            line: line_idx,
-                            message: "Invalid import declaration".to_string(),
+                }
+            },
+            Token::RenderDecl => {
+                // Parse render from the raw line
+                let trimmed = line.trim();
+                 // Skip "/render"
+                if let Some(rest) = trimmed.strip_prefix("/render") {
+                    let rest = rest.trim_start();
+                    // Find end of type (first whitespace)
+                    if let Some(idx) = rest.find(char::is_whitespace) {
+                        let type_name = &rest[..idx];
+                        let template = rest[idx..].trim_start(); // Keep the rest of the line as template
+                        
+                        self.render_system.add_render(type_name, template);
+                    } else {
+                         return Err(Error::Parse {
+                            location,
+                            message: "Invalid render declaration: missing template".to_string(),
                         });
                     }
+                }
+            },
+            Token::StyleDecl => {
+                let rest = line.trim().strip_prefix("/style").unwrap_or("").trim_start();
+                let (type_name, attributes) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                match style_attributes(attributes) {
+                    Some(attributes) if !type_name.is_empty() && !attributes.is_empty() => {
+                        self.render_system.add_style(type_name, attributes);
+                    }
+                    _ => return Err(Error::Parse {
+                        location,
+                        message: "Invalid style declaration: expected a type and attributes like shape=box".to_string(),
+                    }),
+                }
+            },
+            Token::ImportDecl => {
+                // Parse the filename from the raw line, since paths contain slashes
+                let mut args = line.trim().strip_prefix("/import").unwrap_or("").split_whitespace().peekable();
+                let vocab = args.next_if_eq(&"--vocab").is_some();
+                let filename = match args.next() {
+                    Some(filename) => filename.to_string(),
+                    None => return Err(Error::Parse {
+                        location,
+                        message: "Invalid import declaration: missing filename".to_string(),
+                    }),
+                };
 
-                    let filename = match &tokens[1] {
-                        Token::Word(w) => w.clone(),
-                        _ => return Err(Error::Parse {
-                            line: line_idx,
-                            message: "Invalid import filename".to_string(),
-                        }),
-                    };
+                self.handle_import(&filename, vocab, location, parent_ref)?;
+            },
+            Token::Reference { type_name, ident } => {
+                let obj = self.process_object(
+                    type_name.clone(),
+                    Some(ident.clone()),
+                    &tokens[1..tokens.len()-1],
+                    line,
+                    location,
+                    parent_ref,
+                )?;
+                
+                self.objects.push(obj);
 
-                    self.handle_import(&filename)?;
-                },
-                Token::Reference { type_name, ident } => {
-                    let obj = self.process_object(
-                        type_name.clone(),
-                        Some(ident.clone()),
-                        &tokens[1..tokens.len()-1],
-                        line_idx,
-                        parent_ref,
-                    );
-                    
-                    // Update stack
-                    context_stack.push((indent, Reference { type_name: type_name.clone(), ident: ident.clone() }));
-                    
-                    self.objects.push(obj);
-                },
-                Token::AutoReference(type_name) => {
-                    let obj = self.process_object(
-                        type_name.clone(),
-                        None,
-                        &tokens[1..tokens.len()-1],
-                        line_idx,
-                        parent_ref,
-                    );
-                    self.objects.push(obj);
-                },
-                Token::Word(_) => {
-                    let obj = self.process_object(
-                        "item".to_string(),
-                        None,
-                        &tokens[0..tokens.len()-1],
-                        line_idx,
-                        parent_ref,
-                    );
-                    self.objects.push(obj);
-                },
-                _ => {}
-            }
+                // The defined object becomes the parent of indented lines
+                return Ok(Some(Reference::new(type_name, ident, EdgeKind::Parent)));
+            },
+            Token::AutoReference(type_name) => {
+                let obj = self.process_object(
+                    type_name.clone(),
+                    None,
+                    &tokens[1..tokens.len()-1],
+                    line,
+                    location,
+                    parent_ref,
+                )?;
+                self.objects.push(obj);
+            },
+            Token::Word(_) => {
+                let obj = self.process_object(
+                    "item".to_string(),
+                    None,
+                    &tokens[0..tokens.len()-1],
+                    line,
+                    location,
+                    parent_ref,
+                )?;
+                self.objects.push(obj);
+            },
+            _ => {}
         }
 
+        Ok(None)
+    }
+
+    /// Record an error as a diagnostic, or return it if not collecting
+    fn report(&mut self, err: Error) -> Result<(), Error> {
+        if !self.collect_diagnostics {
+            return Err(err);
+        }
+        self.diagnostics.push(Diagnostic::from(&err));
         Ok(())
     }
 
-    fn handle_import(&mut self, filename: &str) -> Result<(), Error> {
-        // Try to find the file in search paths
-        for path in &self.search_paths {
-            let full_path = path.join(filename);
-            if full_path.exists() {
-                // We need to recurse. To avoid borrowing issues, we create a new parser 
-                // or just call parse_file recursively?
-                // parse_file takes &mut self, so recursion is fine.
-                // We just need to handle infinite loops if needed, but for now simple recursion.
-                
-                // Note: We need to handle relative imports from the *imported* file.
-                // parse_file adds the parent dir to search paths, so it should work.
-                return self.parse_file(full_path);
+    fn handle_import(
+        &mut self,
+        filename: &str,
+        vocab: bool,
+        location: SourceLocation,
+        parent_ref: Option<Reference>,
+    ) -> Result<(), Error> {
+        // Look next to the importing file first, then in the include paths. A
+        // string being parsed has no directory, so it imports relative to the
+        // current directory.
+        let base_dir = match &self.current_file {
+            Some(file) => file.parent().map(Path::to_path_buf).unwrap_or_default(),
+            None => PathBuf::new(),
+        };
+        let candidates: Vec<PathBuf> = std::iter::once(base_dir.join(filename))
+            .chain(self.import_options.include_paths.iter().map(|dir| dir.join(filename)))
+            .collect();
+
+        let path = match candidates.iter().find(|path| path.is_file()) {
+            Some(path) => path.clone(),
+            None => {
+                let tried = candidates
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Err(Error::Import(
+                    PathBuf::from(filename),
+                    location,
+                    Box::new(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("File not found, tried: {}", tried),
+                    ).into())
+                ))
             }
+        };
+
+        let canonical = fs::canonicalize(&path)
+            .map_err(|e| Error::Import(path.clone(), location.clone(), Box::new(e.into())))?;
+        if let Some(importer) = self.import_stack.last().and_then(|p| self.files.get_mut(p)) {
+            importer.imports.push(canonical.clone());
         }
 
-        // Try just the filename directly
-        let path = PathBuf::from(filename);
-        if path.exists() {
-            return self.parse_file(path);
+        // A file importing one of the files it is being imported from would
+        // recurse forever
+        if let Some(start) = self.import_stack.iter().position(|p| *p == canonical) {
+            let mut chain = self.import_stack[start..].to_vec();
+            chain.push(canonical);
+            return Err(Error::ImportCycle(chain, location));
         }
 
-        Err(Error::Import(
-            PathBuf::from(filename), 
-            Box::new(std::io::Error::new(std::io::ErrorKind::NotFound, "File not found").into())
-        ))
-    }
+        // Importing a file again would redefine all its objects, but its
+        // vocabulary can still be imported
+        if self.imported.contains(&canonical) && !self.import_options.include_many {
+            if vocab {
+                if let Some(context) = self.files.get(&canonical) {
+                    self.scope.merge(context.exports.clone());
+                }
+            }
+            return Ok(());
+        }
 
-    fn join_tokens(&self, tokens: &[Token]) -> String {
-        tokens
-            .iter()
-            .map(|t| match t {
-                Token::Word(w) => w.clone(),
-                Token::Reference { type_name, ident } => format!("{}/{}", type_name, ident),
-                Token::AutoReference(t) => format!("{}/", t),
-                _ => " ".to_string(),
-            })
-            .collect::<Vec<_>>()
-            .join(" ")
+        // Imported statements inherit the context and the vocabulary of the
+        // import statement, and only a vocabulary import exports the macros
+        // and types declared by the file back to the importer
+        let previous_parent = std::mem::replace(&mut self.inherited_parent, parent_ref);
+        let importer_scope = self.scope.clone();
+        let result = self.parse_file(path);
+        self.scope = importer_scope;
+        self.inherited_parent = previous_parent;
+
+        if vocab {
+            if let Some(context) = self.files.get(&canonical) {
+                self.scope.merge(context.exports.clone());
+            }
+        }
+        result
     }
 
-    fn extract_references(&self, tokens: &[Token]) -> (HashSet<Reference>, HashSet<Reference>) {
-        let mut refs = HashSet::new();
+    fn extract_references(&self, tokens: &[SpannedToken]) -> (HashSet<Reference>, HashSet<Reference>) {
+        let mut refs: HashSet<Reference> = HashSet::new();
         // Initially all refs are unresolved
-        for token in tokens {
-            if let Token::Reference { type_name, ident } = token {
-                refs.insert(Reference {
-                    type_name: type_name.clone(),
-                    ident: ident.clone(),
-                });
+        for t in tokens {
+            if let Token::Reference { type_name, ident } = &t.token {
+                let kind = mention_kind(self.literal_spans.get(&t.span.start).copied());
+                let mut reference = Reference::new(type_name, ident, kind);
+                if let Some(previous) = refs.take(&reference) {
+                    reference.count += previous.count;
+                }
+                refs.insert(reference);
             }
         }
         (HashSet::new(), refs)
     }
 }
 
-/// Convenience wrapper to maintain backward compatibility if needed, 
-/// though we will update usages.
+/// Get the text of a line from the first of some tokens to the last one before
+/// a comment, verbatim
+fn slice_contents(line: &str, tokens: &[SpannedToken]) -> String {
+    let tokens: Vec<_> = tokens
+        .iter()
+        .take_while(|t| !matches!(t.token, Token::Comment(_) | Token::Eol))
+        .collect();
+    match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => line[first.span.start..last.span.end].to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Get the kind of a mention from its span in the file, if it is written in
+/// the text
+fn mention_kind(span: Option<Span>) -> EdgeKind {
+    match span {
+        Some(_) => EdgeKind::Mention,
+        None => EdgeKind::MacroGenerated,
+    }
+}
+
+/// Match the references of a line after macro expansion with those of its
+/// source, in order, to get their spans in the file by their offset in the
+/// expanded line. References produced by macros have no match.
+fn literal_spans(tokens: &[SpannedToken], source: &CstLine) -> HashMap<usize, Span> {
+    let mut spans = HashMap::new();
+    let mut literal = source.tokens.iter();
+    for token in tokens.iter().filter(|t| matches!(t.token, Token::Reference { .. })) {
+        let mut remaining = literal.clone();
+        if let Some(found) = remaining.find(|t| t.token == token.token) {
+            spans.insert(token.span.start, found.span);
+            literal = remaining;
+        }
+    }
+    spans
+}
+
+/// Parse the attributes of a style rule, like `shape=box label="A b"`
+fn style_attributes(text: &str) -> Option<StyleAttributes> {
+    let mut attributes = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let (name, value) = rest.split_once('=')?;
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+        let (value, after) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"')?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => value.split_at(value.find(char::is_whitespace).unwrap_or(value.len())),
+        };
+        if !after.is_empty() && !after.starts_with(char::is_whitespace) {
+            return None;
+        }
+        attributes.push((name.to_string(), value.to_string()));
+        rest = after.trim_start();
+    }
+    Some(attributes)
+}
+
+/// Parse a file containing Oblique code, and the files it imports
 pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<(Vec<Type>, Vec<Object>, RenderSystem), Error> {
     let mut parser = Parser::new();
     parser.parse_file(path)?;
@@ -0,0 +1,303 @@
+//! Protocol buffers of the database, with the schema of `oblique/data.proto`
+//!
+//! The messages are those of the C++ implementation, generated from the schema
+//! by the build script, and written and read here in the binary wire format
+//! and in the text format, so that databases can be passed between the Rust,
+//! C++ and Python tools, or saved and loaded again without parsing their files.
+//!
+//! The schema is that of the C++ parser, which knows nothing of the counts of
+//! links, the render rules or the files of objects: a reference is written
+//! once for each time it occurs, and locations only keep their line. Objects
+//! created for references have line 0 and no contents, as in the C++ output.
+
+use crate::ast::{EdgeKind, Object, ObjectId, ObjectOrigin, Reference, SourceLocation, Type, TypeFlavor};
+use crate::database::Database;
+use crate::error::{Diagnostic, Error};
+use protobuf::{EnumOrUnknown, Message, MessageField};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/proto/mod.rs"));
+}
+
+/// The messages of `data.proto`
+pub use generated::data as messages;
+
+/// The extensions of files in the binary format
+const BINARY_EXTENSIONS: &[&str] = &["pb", "binpb"];
+
+/// The extensions of files in the text format
+const TEXT_EXTENSIONS: &[&str] = &["textproto", "txtpb", "pbtxt"];
+
+/// Check whether a file holds a database in the binary or text format, by its
+/// extension
+pub fn is_proto_file(path: &Path) -> bool {
+    has_extension(path, BINARY_EXTENSIONS) || is_text_file(path)
+}
+
+/// Check whether a file holds a database in the text format, by its extension
+pub fn is_text_file(path: &Path) -> bool {
+    has_extension(path, TEXT_EXTENSIONS)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| extensions.contains(&e))
+}
+
+fn invalid(message: impl ToString) -> Error {
+    Error::Proto(message.to_string())
+}
+
+/// Encode a database in the binary format
+pub fn encode(db: &Database) -> Vec<u8> {
+    to_message(db).write_to_bytes().expect("the schema has no required fields")
+}
+
+/// Decode a database in the binary format. Nested messages are limited by the
+/// recursion limit of the protobuf library.
+pub fn decode(bytes: &[u8]) -> Result<Database, Error> {
+    from_message(&messages::Database::parse_from_bytes(bytes).map_err(invalid)?)
+}
+
+/// Write a database in the text format
+pub fn to_text(db: &Database) -> String {
+    protobuf::text_format::print_to_string_pretty(&to_message(db))
+}
+
+/// Parse a database in the text format. Unknown fields are errors, so nested
+/// messages are limited by the schema.
+pub fn parse_text(text: &str) -> Result<Database, Error> {
+    from_message(&protobuf::text_format::parse_from_str::<messages::Database>(text).map_err(invalid)?)
+}
+
+/// Get the message of the types, objects and diagnostics of a database, in
+/// sorted order
+pub fn to_message(db: &Database) -> messages::Database {
+    let lineno = |location: &Option<SourceLocation>| {
+        location.as_ref().map(|l| i32::try_from(l.line).unwrap_or(i32::MAX))
+    };
+    let to_ref = |id: &ObjectId| messages::Ref {
+        type_: Some(id.type_name.clone()),
+        ident: id.ident.clone(),
+        ..Default::default()
+    };
+    let to_refs = |references: &HashSet<Reference>| {
+        let mut references: Vec<&Reference> = references.iter().collect();
+        references.sort();
+        references
+            .into_iter()
+            .flat_map(|r| {
+                // Mentions are left as the default, as written by the C++ parser
+                let kind = match r.kind {
+                    EdgeKind::Parent => Some(messages::RefKind::PARENT),
+                    EdgeKind::Mention => None,
+                    EdgeKind::MacroGenerated => Some(messages::RefKind::MACRO_GENERATED),
+                };
+                let message = messages::Ref { kind: kind.map(EnumOrUnknown::new), ..to_ref(&ObjectId::from(r)) };
+                std::iter::repeat_n(message, r.count)
+            })
+            .collect()
+    };
+
+    let mut types: Vec<&Type> = db.types.values().collect();
+    types.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut objects: Vec<&Object> = db.objects.values().collect();
+    objects.sort_by(|a, b| a.id.cmp(&b.id));
+
+    messages::Database {
+        type_: types
+            .into_iter()
+            .map(|t| messages::Type {
+                lineno: lineno(&t.location),
+                type_: Some(t.name.clone()),
+                contents: Some(t.contents.clone()),
+                flavor: Some(EnumOrUnknown::new(match t.flavor {
+                    TypeFlavor::Strict => messages::TypeFlavor::STRICT,
+                    TypeFlavor::Lazy => messages::TypeFlavor::LAZY,
+                    TypeFlavor::Ignore => messages::TypeFlavor::IGNORE,
+                })),
+                ..Default::default()
+            })
+            .collect(),
+        object: objects
+            .into_iter()
+            .map(|o| messages::Object {
+                id: MessageField::some(to_ref(&o.id)),
+                lineno: if o.is_placeholder() { Some(0) } else { lineno(&o.location) },
+                contents: (!o.is_placeholder()).then(|| o.contents.clone()),
+                refs: to_refs(&o.refs),
+                unresolved_refs: to_refs(&o.unresolved_refs),
+                ..Default::default()
+            })
+            .collect(),
+        error: db
+            .diagnostics
+            .iter()
+            .map(|d| messages::Error {
+                filename: d.location.as_ref().and_then(|l| l.file.as_ref()).map(|f| f.display().to_string()),
+                lineno: lineno(&d.location),
+                error_message: Some(d.message.clone()),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Build a database of the types, objects and errors of a message, with its
+/// references already resolved
+pub fn from_message(message: &messages::Database) -> Result<Database, Error> {
+    let mut db = Database::new();
+    for t in &message.type_ {
+        db.add_type(Type {
+            name: t.type_.clone().ok_or_else(|| invalid("type without a name"))?,
+            contents: t.contents().to_string(),
+            flavor: match t.flavor() {
+                messages::TypeFlavor::STRICT => TypeFlavor::Strict,
+                messages::TypeFlavor::LAZY => TypeFlavor::Lazy,
+                messages::TypeFlavor::IGNORE => TypeFlavor::Ignore,
+            },
+            location: location(None, t.lineno),
+        });
+    }
+
+    // Objects with identifiers first, so that generated ones skip them
+    let (explicit, generated): (Vec<&messages::Object>, Vec<&messages::Object>) =
+        message.object.iter().partition(|o| o.id.has_ident());
+    for o in explicit.into_iter().chain(generated) {
+        let type_name = o.id.type_.clone().ok_or_else(|| invalid("object without a type"))?;
+        let placeholder = o.lineno() == 0 && o.contents().is_empty();
+        db.add_object(Object {
+            id: ObjectId { type_name, ident: o.id.ident.clone() },
+            contents: o.contents().to_string(),
+            refs: references(&o.refs)?,
+            unresolved_refs: references(&o.unresolved_refs)?,
+            occurrences: Vec::new(),
+            location: location(None, o.lineno),
+            origin: if placeholder { ObjectOrigin::LazyCreated } else { ObjectOrigin::Explicit },
+        })?;
+    }
+
+    db.diagnostics = message
+        .error
+        .iter()
+        .map(|e| Diagnostic {
+            location: location(e.filename.as_deref(), e.lineno),
+            message: e.error_message().to_string(),
+        })
+        .collect();
+    db.rebuild_referrers();
+    Ok(db)
+}
+
+/// Get the location of a line number, which is 0 or unset for none. The schema
+/// has no columns, so locations are at the start of their line.
+fn location(file: Option<&str>, lineno: Option<i32>) -> Option<SourceLocation> {
+    let line = usize::try_from(lineno.unwrap_or(0)).unwrap_or(0);
+    (line > 0 || file.is_some()).then(|| SourceLocation { file: file.map(PathBuf::from), line, column: 1 })
+}
+
+/// Get the references of a list of them, counting those that are repeated
+fn references(refs: &[messages::Ref]) -> Result<HashSet<Reference>, Error> {
+    let mut references: HashSet<Reference> = HashSet::new();
+    for r in refs {
+        let (Some(type_name), Some(ident)) = (&r.type_, &r.ident) else {
+            return Err(invalid("reference without a type or an identifier"));
+        };
+        let kind = match r.kind() {
+            messages::RefKind::MENTION => EdgeKind::Mention,
+            messages::RefKind::PARENT => EdgeKind::Parent,
+            messages::RefKind::MACRO_GENERATED => EdgeKind::MacroGenerated,
+        };
+        let mut reference = Reference::new(type_name, ident, kind);
+        if let Some(previous) = references.take(&reference) {
+            reference.count += previous.count;
+        }
+        references.insert(reference);
+    }
+    Ok(references)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::database;
+
+    #[test]
+    fn test_binary_format() {
+        let message = messages::Database {
+            type_: vec![messages::Type {
+                lineno: Some(-1),
+                type_: Some("t".to_string()),
+                flavor: Some(EnumOrUnknown::new(messages::TypeFlavor::LAZY)),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        // The same bytes as the C++ library: a type with its line, name and
+        // flavor, a negative number taking ten bytes
+        let bytes = message.write_to_bytes().unwrap();
+        assert_eq!(bytes, b"\x0a\x10\x08\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01\x12\x01t\x20\x01");
+        assert_eq!(decode(&bytes).unwrap().types["t"].flavor, TypeFlavor::Lazy);
+
+        // Truncated messages and deeply nested groups are errors
+        assert!(matches!(decode(&bytes[..bytes.len() - 1]), Err(Error::Proto(_))));
+        assert!(matches!(decode(&[0x7b; 100_000]), Err(Error::Proto(_))));
+    }
+
+    #[test]
+    fn test_text_format() {
+        let db = parse_text(
+            r#"
+            # From the tests of the C++ parser
+            type {type: "item" contents: "Item type" flavor: LAZY}
+            object {id {type: "item" ident: "a"} contents: "Conquer 'the \"world\"\x21'"}
+            object {id {type: "t" ident: "b"} refs {type: "item" ident: "a" kind: PARENT}}
+            error {lineno: 2 error_message: "Bad\n"}
+            "#,
+        )
+        .unwrap();
+        let a = ObjectId { type_name: "item".to_string(), ident: Some("a".to_string()) };
+        let b = ObjectId { type_name: "t".to_string(), ident: Some("b".to_string()) };
+        assert_eq!(db.types["item"].flavor, TypeFlavor::Lazy);
+        assert_eq!(db.objects[&a].contents, "Conquer 'the \"world\"!'");
+        assert_eq!(db.parent_of(&b), Some(a));
+        assert_eq!(db.diagnostics[0].message, "Bad\n");
+        assert_eq!(to_message(&parse_text(&to_text(&db)).unwrap()), to_message(&db));
+
+        for text in ["type {", "object { name: \"a\" }", "type { flavor: LOOSE }", "object { id { id { } } }"] {
+            assert!(matches!(parse_text(text), Err(Error::Proto(_))), "{}", text);
+        }
+        assert!(matches!(parse_text("object { contents: \"x\" }"), Err(Error::Proto(m)) if m == "object without a type"));
+    }
+
+    #[test]
+    fn test_database_round_trip() {
+        let db = database("/type/p Project\n/lazytype/u User\np/a A for u/joe and u/joe\n  Do it\nitem/9 Nine\n");
+        let message = to_message(&db);
+        assert_eq!(message.object.iter().map(|o| o.id.ident()).collect::<Vec<_>>(), ["9", "_1", "a", "joe"]);
+        let joe = &message.object[3];
+        assert_eq!((joe.lineno, &joe.contents), (Some(0), &None));
+        // Repeated references are written once for each time, and only the
+        // kinds of those other than mentions
+        assert_eq!(message.object[2].refs.len(), 2);
+        assert_eq!(message.object[1].refs[0].kind(), messages::RefKind::PARENT);
+        assert_eq!(message.object[2].refs[0].kind, None);
+
+        let loaded = decode(&encode(&db)).unwrap();
+        assert_eq!(loaded.objects.len(), db.objects.len());
+        let a = ObjectId { type_name: "p".to_string(), ident: Some("a".to_string()) };
+        let joe = ObjectId { type_name: "u".to_string(), ident: Some("joe".to_string()) };
+        let item = ObjectId { type_name: "item".to_string(), ident: Some("_1".to_string()) };
+        assert_eq!(loaded.objects[&a].contents, "A for u/joe and u/joe");
+        assert_eq!(loaded.objects[&a].refs.iter().next().unwrap().count, 2);
+        assert_eq!(loaded.objects[&a].lineno(), Some(3));
+        assert!(loaded.objects[&joe].is_placeholder());
+        assert_eq!(loaded.referrers(&joe).collect::<Vec<_>>(), [&a]);
+        assert_eq!(loaded.parent_of(&item), Some(a.clone()));
+        assert_eq!(loaded.children_of(&a), [&item]);
+        assert_eq!(loaded.types["p"].contents, "Project");
+        assert_eq!(to_message(&loaded), message);
+    }
+}
@@ -0,0 +1,693 @@
+//! Query language for the Oblique database
+//!
+//! Queries select objects from a `Database` with a small SQL-like syntax:
+//!
+//! ```text
+//! SELECT <type>[, <type>...] | *
+//!   [WHERE <predicate>]
+//!   [GROUP BY <type> | type]
+//!   [ORDER BY type | ident | contents | lineno | file [ASC | DESC]]
+//!   [LIMIT <n>]
+//! ```
+//!
+//! Clauses after `SELECT` may appear in any order, and keywords are case
+//! insensitive. Predicates are combined with `and`, `or`, `not` and
+//! parentheses, and are one of:
+//!
+//! - `type = <name>` (or `type:<name>`, `type != <name>`)
+//! - `ident = <ident>` (or `ident:<ident>`, `ident != <ident>`)
+//! - `contents ~ "<text>"` (or `contents contains "<text>"`), a substring test,
+//!   which also applies to the other fields, e.g. `file ~ "team-alpha"`
+//! - `references <type>/<ident>`, or just `<type>/<ident>`
+//! - `referenced by <type>/<ident>`, using the database's backlinks and
+//!   ignoring parent edges
+//!
+//! A query that does not start with `SELECT` is treated as a bare predicate
+//! over all objects, so `type:task` is equivalent to `SELECT * WHERE type = task`.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use crate::ast::{EdgeKind, Object, ObjectId, Reference};
+use crate::database::Database;
+use crate::error::Error;
+
+/// A field of an object that can be tested or sorted on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// The type name of the object
+    Type,
+
+    /// The identifier of the object
+    Ident,
+
+    /// The contents of the object
+    Contents,
+
+    /// The line number where the object was defined
+    Lineno,
+
+    /// The file where the object was defined
+    File,
+}
+
+/// A predicate over objects, as found in a `WHERE` clause
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Predicate {
+    /// The field equals the value
+    Equals(Field, String),
+
+    /// The field contains the value as a substring
+    Contains(Field, String),
+
+    /// The object mentions the given object, in its contents or through a
+    /// macro; being indented under it is not a reference
+    References(Reference),
+
+    /// The object is mentioned by the given object
+    ReferencedBy(Reference),
+
+    /// Both predicates hold
+    And(Box<Predicate>, Box<Predicate>),
+
+    /// Either predicate holds
+    Or(Box<Predicate>, Box<Predicate>),
+
+    /// The predicate does not hold
+    Not(Box<Predicate>),
+}
+
+/// How the results of a query are grouped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupBy {
+    /// Group by the type of each object
+    Type,
+
+    /// Group by the referenced objects of the given type
+    Referenced(String),
+}
+
+/// A parsed query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    /// The selected types; empty selects all types
+    pub select: Vec<String>,
+
+    /// The filter applied to the selected objects
+    pub filter: Option<Predicate>,
+
+    /// The grouping of the results
+    pub group_by: Option<GroupBy>,
+
+    /// The sort field and whether the order is descending
+    pub order_by: Option<(Field, bool)>,
+
+    /// The maximum number of objects returned
+    pub limit: Option<usize>,
+}
+
+/// A group of objects in a query result
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryGroup<'a> {
+    /// The key of the group, or `None` for ungrouped results and for objects
+    /// that have no reference of the grouping type
+    pub key: Option<String>,
+
+    /// The objects in the group, in the requested order
+    pub objects: Vec<&'a Object>,
+}
+
+/// The result of running a query against a database
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryResult<'a> {
+    /// The groups of matching objects; a single group if not grouped
+    pub groups: Vec<QueryGroup<'a>>,
+}
+
+impl<'a> QueryResult<'a> {
+    /// Iterate over all the objects in the result, across groups
+    pub fn objects(&self) -> impl Iterator<Item = &'a Object> + '_ {
+        self.groups.iter().flat_map(|g| g.objects.iter().copied())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum QueryToken {
+    Word(String),
+    Quoted(String),
+    Comma,
+    LParen,
+    RParen,
+    Equals,
+    NotEquals,
+    Colon,
+    Tilde,
+}
+
+lazy_static! {
+    static ref QUERY_TOKEN_RE: Regex =
+        Regex::new(r#"^(?:"((?:[^"\\]|\\.)*)"|(!=)|([,()=:~])|([^\s,()=:~!"]+))"#).unwrap();
+}
+
+fn tokenize_query(input: &str) -> Result<Vec<QueryToken>, Error> {
+    let mut tokens = Vec::new();
+    let mut remaining = input.trim_start();
+
+    while !remaining.is_empty() {
+        let captures = QUERY_TOKEN_RE
+            .captures(remaining)
+            .ok_or_else(|| Error::Query(format!("Unexpected input at '{}'", remaining)))?;
+
+        if let Some(quoted) = captures.get(1) {
+            let text = quoted.as_str().replace("\\\"", "\"").replace("\\\\", "\\");
+            tokens.push(QueryToken::Quoted(text));
+        } else if captures.get(2).is_some() {
+            tokens.push(QueryToken::NotEquals);
+        } else if let Some(punct) = captures.get(3) {
+            tokens.push(match punct.as_str() {
+                "," => QueryToken::Comma,
+                "(" => QueryToken::LParen,
+                ")" => QueryToken::RParen,
+                "=" => QueryToken::Equals,
+                ":" => QueryToken::Colon,
+                _ => QueryToken::Tilde,
+            });
+        } else {
+            tokens.push(QueryToken::Word(captures[4].to_string()));
+        }
+
+        remaining = remaining[captures[0].len()..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct QueryParser {
+    tokens: Vec<QueryToken>,
+    pos: usize,
+}
+
+impl QueryParser {
+    fn peek(&self) -> Option<&QueryToken> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<QueryToken> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Check whether the next token is the given keyword, without consuming it
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(QueryToken::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Consume the next token if it is the given keyword
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.at_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", keyword.to_uppercase())))
+        }
+    }
+
+    fn expect_word(&mut self, what: &str) -> Result<String, Error> {
+        match self.next() {
+            Some(QueryToken::Word(w)) => Ok(w),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected(what))
+            }
+        }
+    }
+
+    fn expect_value(&mut self, what: &str) -> Result<String, Error> {
+        match self.next() {
+            Some(QueryToken::Word(w)) | Some(QueryToken::Quoted(w)) => Ok(w),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected(what))
+            }
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> Error {
+        match self.peek() {
+            Some(token) => Error::Query(format!("Expected {}, found {:?}", expected, token)),
+            None => Error::Query(format!("Expected {}, found end of query", expected)),
+        }
+    }
+
+    fn parse_query(&mut self) -> Result<Query, Error> {
+        let mut query = Query {
+            select: Vec::new(),
+            filter: None,
+            group_by: None,
+            order_by: None,
+            limit: None,
+        };
+
+        if !self.eat_keyword("select") {
+            // A bare predicate over all objects
+            query.filter = Some(self.parse_or()?);
+            if self.peek().is_some() {
+                return Err(self.unexpected("end of query"));
+            }
+            return Ok(query);
+        }
+
+        query.select = self.parse_select_list()?;
+
+        while self.peek().is_some() {
+            if self.eat_keyword("where") {
+                if query.filter.is_some() {
+                    return Err(Error::Query("Duplicate WHERE clause".to_string()));
+                }
+                query.filter = Some(self.parse_or()?);
+            } else if self.eat_keyword("group") {
+                self.expect_keyword("by")?;
+                if query.group_by.is_some() {
+                    return Err(Error::Query("Duplicate GROUP BY clause".to_string()));
+                }
+                let name = self.expect_word("a type name")?;
+                query.group_by = Some(if name.eq_ignore_ascii_case("type") {
+                    GroupBy::Type
+                } else {
+                    GroupBy::Referenced(name)
+                });
+            } else if self.eat_keyword("order") {
+                self.expect_keyword("by")?;
+                if query.order_by.is_some() {
+                    return Err(Error::Query("Duplicate ORDER BY clause".to_string()));
+                }
+                let name = self.expect_word("a field name")?;
+                let field = parse_field(&name)?;
+                let descending = if self.eat_keyword("desc") {
+                    true
+                } else {
+                    self.eat_keyword("asc");
+                    false
+                };
+                query.order_by = Some((field, descending));
+            } else if self.eat_keyword("limit") {
+                if query.limit.is_some() {
+                    return Err(Error::Query("Duplicate LIMIT clause".to_string()));
+                }
+                let value = self.expect_word("a number")?;
+                let limit = value
+                    .parse()
+                    .map_err(|_| Error::Query(format!("Invalid LIMIT value '{}'", value)))?;
+                query.limit = Some(limit);
+            } else {
+                return Err(self.unexpected("WHERE, GROUP BY, ORDER BY or LIMIT"));
+            }
+        }
+
+        Ok(query)
+    }
+
+    fn parse_select_list(&mut self) -> Result<Vec<String>, Error> {
+        let first = self.expect_word("a type name or '*'")?;
+        if first == "*" {
+            return Ok(Vec::new());
+        }
+
+        let mut types = vec![first];
+        while self.peek() == Some(&QueryToken::Comma) {
+            self.pos += 1;
+            types.push(self.expect_word("a type name")?);
+        }
+        Ok(types)
+    }
+
+    fn parse_or(&mut self) -> Result<Predicate, Error> {
+        let mut left = self.parse_and()?;
+        while self.eat_keyword("or") {
+            let right = self.parse_and()?;
+            left = Predicate::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Predicate, Error> {
+        let mut left = self.parse_unary()?;
+        while self.eat_keyword("and") {
+            let right = self.parse_unary()?;
+            left = Predicate::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Predicate, Error> {
+        if self.eat_keyword("not") {
+            return Ok(Predicate::Not(Box::new(self.parse_unary()?)));
+        }
+
+        if self.peek() == Some(&QueryToken::LParen) {
+            self.pos += 1;
+            let inner = self.parse_or()?;
+            if self.next() != Some(QueryToken::RParen) {
+                self.pos -= 1;
+                return Err(self.unexpected("')'"));
+            }
+            return Ok(inner);
+        }
+
+        let word = self.expect_word("a predicate")?;
+
+        if word.eq_ignore_ascii_case("references") {
+            let target = self.expect_word("an object reference")?;
+            return parse_reference(&target).map(Predicate::References);
+        }
+
+        if word.eq_ignore_ascii_case("referenced") {
+            self.expect_keyword("by")?;
+            let source = self.expect_word("an object reference")?;
+            return parse_reference(&source).map(Predicate::ReferencedBy);
+        }
+
+        if let Ok(field) = parse_field(&word) {
+            return match self.next() {
+                Some(QueryToken::Equals) | Some(QueryToken::Colon) => {
+                    Ok(Predicate::Equals(field, self.expect_value("a value")?))
+                }
+                Some(QueryToken::NotEquals) => Ok(Predicate::Not(Box::new(Predicate::Equals(
+                    field,
+                    self.expect_value("a value")?,
+                )))),
+                Some(QueryToken::Tilde) => {
+                    Ok(Predicate::Contains(field, self.expect_value("a value")?))
+                }
+                Some(QueryToken::Word(w)) if w.eq_ignore_ascii_case("contains") => {
+                    Ok(Predicate::Contains(field, self.expect_value("a value")?))
+                }
+                _ => {
+                    self.pos -= 1;
+                    Err(self.unexpected("'=', '!=', ':', '~' or 'contains'"))
+                }
+            };
+        }
+
+        // A bare object reference is shorthand for "references <ref>"
+        parse_reference(&word).map(Predicate::References)
+    }
+}
+
+fn parse_field(name: &str) -> Result<Field, Error> {
+    match name.to_ascii_lowercase().as_str() {
+        "type" => Ok(Field::Type),
+        "ident" | "id" => Ok(Field::Ident),
+        "contents" => Ok(Field::Contents),
+        "lineno" | "line" => Ok(Field::Lineno),
+        "file" => Ok(Field::File),
+        _ => Err(Error::Query(format!("Unknown field '{}'", name))),
+    }
+}
+
+fn parse_reference(text: &str) -> Result<Reference, Error> {
+    match text.split_once('/') {
+        Some((type_name, ident)) if !type_name.is_empty() && !ident.is_empty() => Ok(Reference::new(type_name, ident, EdgeKind::Mention)),
+        _ => Err(Error::Query(format!(
+            "Invalid object reference '{}', expected <type>/<ident>",
+            text
+        ))),
+    }
+}
+
+/// Parse a query string
+pub fn parse_query(input: &str) -> Result<Query, Error> {
+    let tokens = tokenize_query(input)?;
+    if tokens.is_empty() {
+        return Err(Error::Query("Empty query".to_string()));
+    }
+    QueryParser { tokens, pos: 0 }.parse_query()
+}
+
+fn field_value(object: &Object, field: Field) -> String {
+    match field {
+        Field::Type => object.id.type_name.clone(),
+        Field::Ident => object.id.ident.clone().unwrap_or_default(),
+        Field::Contents => object.contents.clone(),
+        Field::Lineno => object.lineno().map(|l| l.to_string()).unwrap_or_default(),
+        Field::File => object
+            .location
+            .as_ref()
+            .and_then(|l| l.file.as_ref())
+            .map(|f| f.display().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// Compare two strings, ordering them numerically if both are numbers
+fn compare_values(a: &str, b: &str) -> Ordering {
+    match (a.parse::<u64>(), b.parse::<u64>()) {
+        (Ok(x), Ok(y)) => x.cmp(&y),
+        _ => a.cmp(b),
+    }
+}
+
+fn compare_objects(a: &Object, b: &Object, field: Field) -> Ordering {
+    let primary = match field {
+        // Sort by position in the sources, rather than by line alone
+        Field::Lineno | Field::File => a.location.cmp(&b.location),
+        _ => compare_values(&field_value(a, field), &field_value(b, field)),
+    };
+    primary
+        .then_with(|| a.id.type_name.cmp(&b.id.type_name))
+        .then_with(|| {
+            compare_values(
+                a.id.ident.as_deref().unwrap_or(""),
+                b.id.ident.as_deref().unwrap_or(""),
+            )
+        })
+}
+
+impl Predicate {
+    /// Check whether the predicate holds for an object of the database
+    pub fn matches(&self, db: &Database, object: &Object) -> bool {
+        match self {
+            Predicate::Equals(field, value) => field_value(object, *field) == *value,
+            Predicate::Contains(field, value) => field_value(object, *field).contains(value.as_str()),
+            Predicate::References(reference) => object
+                .refs
+                .iter()
+                .chain(&object.unresolved_refs)
+                .any(|r| r.kind != EdgeKind::Parent && r.same_target(reference)),
+            Predicate::ReferencedBy(reference) => {
+                let source = ObjectId::from(reference);
+                if !db.referrers(&object.id).any(|id| *id == source) {
+                    return false;
+                }
+                // The index does not record edge kinds, so check that the
+                // source refers to the object other than as its parent
+                let target = Reference::new(
+                    object.id.type_name.clone(),
+                    object.id.ident.clone().unwrap_or_default(),
+                    EdgeKind::Mention,
+                );
+                db.objects
+                    .get(&source)
+                    .is_some_and(|source| source.refs.iter().any(|r| r.kind != EdgeKind::Parent && r.same_target(&target)))
+            }
+            Predicate::And(left, right) => left.matches(db, object) && right.matches(db, object),
+            Predicate::Or(left, right) => left.matches(db, object) || right.matches(db, object),
+            Predicate::Not(inner) => !inner.matches(db, object),
+        }
+    }
+}
+
+impl Query {
+    /// Run the query against a database
+    pub fn execute<'a>(&self, db: &'a Database) -> QueryResult<'a> {
+        let mut objects: Vec<&Object> = db
+            .objects
+            .values()
+            .filter(|o| self.select.is_empty() || self.select.contains(&o.id.type_name))
+            .filter(|o| self.filter.as_ref().is_none_or(|p| p.matches(db, o)))
+            .collect();
+
+        let (field, descending) = self.order_by.unwrap_or((Field::Type, false));
+        objects.sort_by(|a, b| {
+            let ordering = compare_objects(a, b, field);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+
+        if let Some(limit) = self.limit {
+            objects.truncate(limit);
+        }
+
+        let groups = match &self.group_by {
+            None => vec![QueryGroup { key: None, objects }],
+            Some(group_by) => {
+                let mut keyed: BTreeMap<String, Vec<&Object>> = BTreeMap::new();
+                let mut ungrouped = Vec::new();
+                for object in objects {
+                    let keys = group_keys(object, group_by);
+                    if keys.is_empty() {
+                        ungrouped.push(object);
+                    }
+                    for key in keys {
+                        keyed.entry(key).or_default().push(object);
+                    }
+                }
+
+                let mut groups: Vec<QueryGroup> = keyed
+                    .into_iter()
+                    .map(|(key, objects)| QueryGroup { key: Some(key), objects })
+                    .collect();
+                if !ungrouped.is_empty() {
+                    groups.push(QueryGroup { key: None, objects: ungrouped });
+                }
+                groups
+            }
+        };
+
+        QueryResult { groups }
+    }
+}
+
+fn group_keys(object: &Object, group_by: &GroupBy) -> Vec<String> {
+    match group_by {
+        GroupBy::Type => vec![object.id.type_name.clone()],
+        GroupBy::Referenced(type_name) => {
+            let mut keys: Vec<String> = object
+                .refs
+                .iter()
+                .chain(object.unresolved_refs.iter())
+                .filter(|r| r.kind != EdgeKind::Parent && &r.type_name == type_name)
+                .map(|r| format!("{}/{}", r.type_name, r.ident))
+                .collect();
+            // Objects of the grouping type are grouped under themselves
+            if &object.id.type_name == type_name {
+                if let Some(ident) = &object.id.ident {
+                    keys.push(format!("{}/{}", type_name, ident));
+                }
+            }
+            keys.sort();
+            keys.dedup();
+            keys
+        }
+    }
+}
+
+/// Parse and run a query against a database
+pub fn run_query<'a>(db: &'a Database, input: &str) -> Result<QueryResult<'a>, Error> {
+    Ok(parse_query(input)?.execute(db))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::tests::database;
+
+    fn idents(result: &QueryResult) -> Vec<String> {
+        result
+            .objects()
+            .map(|o| format!("{}/{}", o.id.type_name, o.id.ident.as_deref().unwrap_or("")))
+            .collect()
+    }
+
+    const INPUT: &str = r#"
+    /lazytype/q Quarter
+    /lazytype/f Flag
+    /type/o Objective
+    o/embed Embedding work for q/q2 f/important
+    o/docs Write the docs for q/q2
+    o/launch Launch in q/q3 f/important
+    "#;
+
+    #[test]
+    fn test_parse_query_clauses() {
+        let query = parse_query("Select o, q group by o where q/q2 and f/important limit 3").unwrap();
+        assert_eq!(query.select, vec!["o".to_string(), "q".to_string()]);
+        assert_eq!(query.group_by, Some(GroupBy::Referenced("o".to_string())));
+        assert_eq!(query.limit, Some(3));
+        assert!(matches!(query.filter, Some(Predicate::And(_, _))));
+
+        assert!(parse_query("select o where").is_err());
+        assert!(parse_query("select o order by colour").is_err());
+        assert!(parse_query("").is_err());
+    }
+
+    #[test]
+    fn test_where_references() {
+        let db = database(INPUT);
+        let result = run_query(&db, "select o where q/q2 and f/important").unwrap();
+        assert_eq!(idents(&result), vec!["o/embed"]);
+
+        let result = run_query(&db, "select o where references q/q2 and not f/important").unwrap();
+        assert_eq!(idents(&result), vec!["o/docs"]);
+
+        let result = run_query(&db, "referenced by o/launch").unwrap();
+        assert_eq!(idents(&result), vec!["f/important", "q/q3"]);
+    }
+
+    #[test]
+    fn test_references_ignore_parents() {
+        let db = database("/lazytype/q Quarter\nq/q2\n  Indented only\n  Mentions q/q2\n");
+        let result = run_query(&db, "references q/q2").unwrap();
+        assert_eq!(idents(&result), vec!["item/_2"]);
+
+        let result = run_query(&db, "referenced by item/_1").unwrap();
+        assert!(idents(&result).is_empty());
+        let result = run_query(&db, "referenced by item/_2").unwrap();
+        assert_eq!(idents(&result), vec!["q/q2"]);
+    }
+
+    #[test]
+    fn test_group_by_ignores_parents() {
+        let db = database("/lazytype/q Quarter\nq/q2\n  Indented only\n  Mentions q/q2\n");
+        let result = run_query(&db, "select item group by q").unwrap();
+        let groups: Vec<_> = result
+            .groups
+            .iter()
+            .map(|g| (g.key.as_deref(), g.objects.iter().map(|o| o.id.ident.as_deref().unwrap()).collect::<Vec<_>>()))
+            .collect();
+        assert_eq!(groups, vec![(Some("q/q2"), vec!["_2"]), (None, vec!["_1"])]);
+    }
+
+    #[test]
+    fn test_bare_predicate_and_fields() {
+        let db = database(INPUT);
+        let result = run_query(&db, "type:q").unwrap();
+        assert_eq!(idents(&result), vec!["q/q2", "q/q3"]);
+
+        let result = run_query(&db, r#"contents ~ "docs" or ident = launch"#).unwrap();
+        assert_eq!(idents(&result), vec!["o/docs", "o/launch"]);
+    }
+
+    #[test]
+    fn test_group_order_limit() {
+        let db = database(INPUT);
+        let result = run_query(&db, "select o group by q order by ident desc").unwrap();
+        let keys: Vec<_> = result.groups.iter().map(|g| g.key.clone().unwrap()).collect();
+        assert_eq!(keys, vec!["q/q2", "q/q3"]);
+        let q2: Vec<_> = result.groups[0].objects.iter().map(|o| o.id.clone()).collect();
+        assert_eq!(
+            q2,
+            vec![
+                ObjectId { type_name: "o".to_string(), ident: Some("embed".to_string()) },
+                ObjectId { type_name: "o".to_string(), ident: Some("docs".to_string()) },
+            ]
+        );
+
+        let result = run_query(&db, "select o order by ident limit 1").unwrap();
+        assert_eq!(idents(&result), vec!["o/docs"]);
+    }
+}
@@ -0,0 +1,266 @@
+//! Renaming objects and types across the files of a database, and writing
+//! generated identifiers into them
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::ast::{ObjectId, Reference, Span};
+use crate::cst::{Cst, CstLine};
+use crate::database::Database;
+use crate::error::{Diagnostic, Error};
+use crate::lexer::{lex_line, Token};
+
+lazy_static! {
+    static ref IDENT_RE: Regex = Regex::new(r"^[\w-]+$").unwrap();
+    static ref TYPE_NAME_RE: Regex = Regex::new(r"^[a-z]+$").unwrap();
+}
+
+/// What to rename
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenameTarget {
+    /// An object, to a new identifier of the same type
+    Object(Reference, String),
+
+    /// A type, to a new name
+    Type(String, String),
+}
+
+/// A replacement of the text of a span of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+/// The edits to the files of a database renaming something, or writing
+/// identifiers
+#[derive(Debug, Clone, Default)]
+pub struct Rename {
+    /// The edits of each file, in source order, by path as used in source
+    /// locations
+    pub edits: BTreeMap<PathBuf, Vec<TextEdit>>,
+
+    /// The references produced by macros rather than written in the text,
+    /// which change only if the macros producing them were rewritten
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Rename {
+    /// Write the edits to the files
+    pub fn apply(&self) -> Result<(), Error> {
+        for (path, edits) in &self.edits {
+            let text = fs::read_to_string(path)?;
+            fs::write(path, apply_edits(&text, edits))?;
+        }
+        Ok(())
+    }
+}
+
+/// Apply edits in source order to a text
+pub fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut end = 0;
+    for edit in edits {
+        output.push_str(&text[end..edit.span.start]);
+        output.push_str(&edit.text);
+        end = edit.span.end;
+    }
+    output.push_str(&text[end..]);
+    output
+}
+
+/// Compute the edits renaming an object or a type: its definition, and every
+/// reference to it written in the files of the database, macro replacements
+/// included. References to it produced by macros are reported as warnings.
+pub fn rename(db: &Database, target: &RenameTarget) -> Result<Rename, Error> {
+    check_target(db, target)?;
+
+    let mut rename = Rename::default();
+    for path in db.source_files() {
+        let text = fs::read_to_string(path)?;
+        let cst = Cst::parse(&text);
+        let edits: Vec<TextEdit> = cst.lines.iter().flat_map(|line| line_edits(&cst, line, target)).collect();
+        if !edits.is_empty() {
+            rename.edits.insert(path.to_path_buf(), edits);
+        }
+    }
+
+    let mut objects: Vec<_> = db.objects.values().collect();
+    objects.sort_by(|a, b| a.location.cmp(&b.location));
+    for object in objects {
+        for occurrence in object.occurrences.iter().filter(|o| o.span.is_none()) {
+            let reference = &occurrence.reference;
+            let matches = match target {
+                RenameTarget::Object(from, _) => reference.same_target(from),
+                RenameTarget::Type(from, _) => reference.type_name == *from,
+            };
+            if matches {
+                rename.warnings.push(Diagnostic {
+                    location: Some(occurrence.location.clone()),
+                    message: format!(
+                        "Reference to '{}/{}' at {} is produced by a macro rather than written in the text",
+                        reference.type_name, reference.ident, occurrence.location
+                    ),
+                });
+            }
+        }
+    }
+
+    Ok(rename)
+}
+
+/// Compute the edits writing the generated identifiers of the objects defined
+/// with an auto-reference into their definitions, e.g. "t/ Task" becomes
+/// "t/_7f3a2c Task", so that they no longer change. Default items, which have
+/// no reference, are left as they are.
+pub fn write_auto_ids(db: &Database) -> Result<Rename, Error> {
+    // The objects by the line defining them
+    let mut defined_at = HashMap::new();
+    for object in db.objects.values() {
+        if let (Some(location), Some(ident)) = (&object.location, &object.id.ident) {
+            defined_at.insert((location.file.clone(), location.line, object.id.type_name.as_str()), ident);
+        }
+    }
+
+    let mut rename = Rename::default();
+    for path in db.source_files() {
+        let text = fs::read_to_string(path)?;
+        let cst = Cst::parse(&text);
+        let mut edits = Vec::new();
+        for line in &cst.lines {
+            if let Token::AutoReference(type_name) = &line.tokens[0].token {
+                let key = (Some(path.to_path_buf()), line.number, type_name.as_str());
+                if let Some(ident) = defined_at.get(&key) {
+                    let end = line.tokens[0].span.end;
+                    edits.push(TextEdit { span: Span::new(end, end), text: ident.to_string() });
+                }
+            }
+        }
+        if !edits.is_empty() {
+            rename.edits.insert(path.to_path_buf(), edits);
+        }
+    }
+    Ok(rename)
+}
+
+/// Check that the thing to rename exists and that its new name is valid and
+/// not taken
+fn check_target(db: &Database, target: &RenameTarget) -> Result<(), Error> {
+    match target {
+        RenameTarget::Object(from, to) => {
+            if !IDENT_RE.is_match(to) {
+                return Err(Error::Rename(format!("'{}' is not a valid identifier", to)));
+            }
+            if !db.objects.contains_key(&ObjectId::from(from)) {
+                return Err(Error::Rename(format!("no object '{}/{}'", from.type_name, from.ident)));
+            }
+            let new = Reference::new(&from.type_name, to, from.kind);
+            if db.objects.contains_key(&ObjectId::from(&new)) {
+                return Err(Error::Rename(format!("'{}/{}' already exists", new.type_name, new.ident)));
+            }
+        }
+        RenameTarget::Type(from, to) => {
+            if !TYPE_NAME_RE.is_match(to) {
+                return Err(Error::Rename(format!("'{}' is not a valid type name", to)));
+            }
+            if db.types.get(from).is_none_or(|t| t.location.is_none()) {
+                return Err(Error::Rename(format!("no declared type '{}'", from)));
+            }
+            if db.types.contains_key(to) {
+                return Err(Error::Rename(format!("type '{}' already exists", to)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Get the edits renaming the target in a line
+fn line_edits(cst: &Cst, line: &CstLine, target: &RenameTarget) -> Vec<TextEdit> {
+    match (&line.tokens[0].token, line.tokens.get(1).map(|t| &t.token), target) {
+        // The replacement of a macro is taken verbatim, so it is lexed again
+        // on its own, the pattern being a regular expression
+        (Token::MacroDecl, _, _) => {
+            let text = cst.line_text(line);
+            let start = line.tokens[0].span.end - line.span.start;
+            let rest = &text[start..];
+            let pattern_start = start + rest.len() - rest.trim_start().len();
+            let pattern_end = text[pattern_start..].find(char::is_whitespace).map_or(text.len(), |i| pattern_start + i);
+            lex_line(&text[pattern_end..])
+                .iter()
+                .filter_map(|t| token_edit(&t.token, t.span.offset(line.span.start + pattern_end), target, true))
+                .collect()
+        }
+        // The type of a render or style rule is a plain word
+        (Token::RenderDecl | Token::StyleDecl, Some(Token::Word(name)), RenameTarget::Type(from, to)) if name == from => {
+            vec![TextEdit { span: line.tokens[1].span, text: to.clone() }]
+        }
+        _ => line.tokens.iter().filter_map(|t| token_edit(&t.token, t.span, target, false)).collect(),
+    }
+}
+
+/// Get the edit renaming the target in a token, if it is in it
+fn token_edit(token: &Token, span: Span, target: &RenameTarget, in_macro: bool) -> Option<TextEdit> {
+    let (span, to) = match (token, target) {
+        (Token::Reference { type_name, ident }, RenameTarget::Object(from, to))
+            if *type_name == from.type_name && *ident == from.ident =>
+        {
+            (Span::new(span.start + type_name.len() + 1, span.end), to)
+        }
+        (Token::Reference { type_name: name, .. } | Token::AutoReference(name), RenameTarget::Type(from, to))
+            if name == from =>
+        {
+            (Span::new(span.start, span.start + name.len()), to)
+        }
+        (
+            Token::TypeDecl(name) | Token::LazyTypeDecl(name) | Token::IgnoreTypeDecl(name),
+            RenameTarget::Type(from, to),
+        ) if name == from => (Span::new(span.end - name.len(), span.end), to),
+        // References with substitutions in macro replacements, like "u/\1",
+        // are plain words
+        (Token::Word(word), RenameTarget::Type(from, to))
+            if in_macro && word.strip_prefix(from.as_str()).is_some_and(|rest| rest.starts_with('/')) =>
+        {
+            (Span::new(span.start, span.start + from.len()), to)
+        }
+        _ => return None,
+    };
+    Some(TextEdit { span, text: to.clone() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::EdgeKind;
+
+    fn edit(text: &str, target: &RenameTarget) -> String {
+        let cst = Cst::parse(text);
+        let edits: Vec<_> = cst.lines.iter().flat_map(|line| line_edits(&cst, line, target)).collect();
+        apply_edits(text, &edits)
+    }
+
+    fn reference(type_name: &str, ident: &str) -> Reference {
+        Reference::new(type_name, ident, EdgeKind::Mention)
+    }
+
+    #[test]
+    fn test_rename_object_in_text() {
+        let target = RenameTarget::Object(reference("p", "old"), "new".to_string());
+        assert_eq!(
+            edit("p/old  The (p/old).\n  t/a See p/old, not p/older # p/old\n", &target),
+            "p/new  The (p/new).\n  t/a See p/new, not p/older # p/old\n"
+        );
+    }
+
+    #[test]
+    fn test_rename_type_in_text() {
+        let target = RenameTarget::Type("t".to_string(), "tsk".to_string());
+        assert_eq!(
+            edit("/type/t Task\n/macro #([a-z]+) t/\\1 t/a\n/render t #\\1\nt/a For t/b\nt/ Auto\nitem/x Not it/t\n", &target),
+            "/type/tsk Task\n/macro #([a-z]+) tsk/\\1 tsk/a\n/render tsk #\\1\ntsk/a For tsk/b\ntsk/ Auto\nitem/x Not it/t\n"
+        );
+    }
+}
@@ -153,9 +153,63 @@ fn test_nested_import_error() {
     let result = parse_string(input);
     
     match result {
-        Err(Error::Import(path, _)) => {
+        Err(Error::Import(path, location, _)) => {
             assert_eq!(path, PathBuf::from("non_existent_file.oblique"));
+            assert_eq!((location.line, location.column), (2, 5));
         },
         _ => panic!("Expected Import error, got {:?}", result),
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_collect_all_diagnostics() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("errors.oblique");
+    std::fs::write(&path, r#"/type/t Task
+/macro ( broken
+t/1 First
+t/1 Again
+t/2 Refers to x/3
+/import missing.oblique
+"#).unwrap();
+
+    // Without diagnostics mode, the first error aborts
+    let mut db = Database::new();
+    assert!(db.import_file(&path).is_err());
+
+    let mut db = Database::new();
+    db.set_collect_diagnostics(true);
+    db.import_file(&path).unwrap();
+
+    let locations: Vec<_> = db.diagnostics.iter().map(|d| d.location.clone().unwrap()).collect();
+    let lines: Vec<_> = locations.iter().map(|l| l.line).collect();
+    assert_eq!(lines, vec![2, 6, 4, 5]);
+    assert!(locations.iter().all(|l| l.file.as_deref() == Some(path.as_path())));
+
+    // The valid definitions are still imported
+    assert!(db.objects.contains_key(&ObjectId { type_name: "t".to_string(), ident: Some("2".to_string()) }));
+}
+
+#[test]
+fn test_auto_ids_skip_explicit_idents() {
+    let dir = tempfile::tempdir().unwrap();
+    let main = dir.path().join("main.oblique");
+    let more = dir.path().join("more.oblique");
+    std::fs::write(&main, "/type/t Task\nt/ First\nt/ Second\nt/1 Explicit\nt/_1 Written\n").unwrap();
+    std::fs::write(&more, "t/2 Imported later\n").unwrap();
+    let id = |ident: &str| ObjectId { type_name: "t".to_string(), ident: Some(ident.to_string()) };
+
+    // Generated identifiers skip those already written into the files
+    let mut db = Database::new();
+    db.import_file(&main).unwrap();
+    assert_eq!(db.objects[&id("1")].contents, "Explicit");
+    assert_eq!(db.objects[&id("_1")].contents, "Written");
+    assert_eq!(db.objects[&id("_2")].contents, "First");
+    assert_eq!(db.objects[&id("_3")].contents, "Second");
+
+    // Defining the same number later leaves the generated ones as they were
+    db.import_file(&more).unwrap();
+    assert_eq!(db.objects[&id("2")].contents, "Imported later");
+    assert_eq!(db.objects[&id("_2")].contents, "First");
+    assert_eq!(db.objects.len(), 5);
+}
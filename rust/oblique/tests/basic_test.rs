@@ -1,4 +1,4 @@
-use oblique::{Database, ObjectId, TypeFlavor};
+use oblique::{Database, EdgeKind, ObjectId, ObjectOrigin, Reference, TypeFlavor};
 
 #[test]
 fn test_basic_parsing() {
@@ -80,3 +80,40 @@ fn test_lazy_types() {
     
     assert!(db.objects.contains_key(&user_id));
 }
+
+#[test]
+fn test_object_origins() {
+    let input = r#"
+    /lazytype/user User
+    /lazytype/team Team
+    task/conquer Conquer the world with user/alice
+    task/ Plan it
+    "#;
+
+    let mut db = Database::new();
+    let (types, mut objects, _) = oblique::parse_string(input).unwrap();
+    for typ in types {
+        db.add_type(typ);
+    }
+
+    // A child of an object that is not defined anywhere, e.g. built by a tool
+    let mut child = objects[0].clone();
+    child.id.ident = Some("child".to_string());
+    child.occurrences.clear();
+    child.unresolved_refs = [Reference::new("team", "red", EdgeKind::Parent)].into();
+    objects.push(child);
+
+    for obj in objects {
+        db.add_object(obj).unwrap();
+    }
+    db.resolve_references().unwrap();
+
+    let origin = |type_name: &str, ident: &str| {
+        db.objects[&ObjectId { type_name: type_name.to_string(), ident: Some(ident.to_string()) }].origin
+    };
+    assert_eq!(origin("task", "conquer"), ObjectOrigin::Explicit);
+    assert_eq!(origin("task", "_1"), ObjectOrigin::AutoId);
+    assert_eq!(origin("user", "alice"), ObjectOrigin::LazyCreated);
+    assert_eq!(origin("team", "red"), ObjectOrigin::ParentImplied);
+    assert_eq!(db.objects.values().filter(|o| o.is_placeholder()).count(), 2);
+}
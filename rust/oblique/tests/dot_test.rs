@@ -0,0 +1,227 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tempfile::tempdir;
+use oblique::{generate_dot, Clustering, Database, DotOptions, ObjectId};
+
+/// A graph read back from DOT: the attributes of each node, and the edges
+#[derive(Debug, Default)]
+struct Graph {
+    nodes: BTreeMap<String, BTreeMap<String, String>>,
+    edges: BTreeSet<(String, String)>,
+}
+
+/// Split DOT into tokens: punctuation, and IDs as they are written, quoted
+/// strings unquoted and HTML strings with their angle brackets
+fn tokenize(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'>') {
+            tokens.push("->".to_string());
+            i += 2;
+        } else if "{}[];,=".contains(c) {
+            tokens.push(c.to_string());
+            i += 1;
+        } else if c == '"' {
+            let mut value = String::new();
+            i += 1;
+            while chars[i] != '"' {
+                if chars[i] == '\\' && chars[i + 1] == '"' {
+                    i += 1;
+                }
+                value.push(chars[i]);
+                i += 1;
+            }
+            tokens.push(value);
+            i += 1;
+        } else if c == '<' {
+            let (mut depth, start) = (0, i);
+            loop {
+                match chars[i] {
+                    '<' => depth += 1,
+                    '>' => depth -= 1,
+                    _ => {}
+                }
+                i += 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || "_.#".contains(chars[i])) {
+                i += 1;
+            }
+            assert!(i > start, "Unexpected character {:?} in {}", c, text);
+            tokens.push(chars[start..i].iter().collect());
+        }
+    }
+    tokens
+}
+
+/// Parse the statements of a graph or subgraph up to its closing brace
+fn parse_statements(tokens: &[String], mut i: usize, graph: &mut Graph) -> usize {
+    let attributes = |mut i: usize| {
+        let mut attributes = BTreeMap::new();
+        if tokens[i] == "[" {
+            i += 1;
+            while tokens[i] != "]" {
+                assert_eq!(tokens[i + 1], "=");
+                attributes.insert(tokens[i].clone(), tokens[i + 2].clone());
+                i += 3;
+                if tokens[i] == "," {
+                    i += 1;
+                }
+            }
+            i += 1;
+        }
+        (attributes, i)
+    };
+
+    while tokens[i] != "}" {
+        if tokens[i] == "subgraph" {
+            assert!(tokens[i + 1].starts_with("cluster_"));
+            assert_eq!(tokens[i + 2], "{");
+            i = parse_statements(tokens, i + 3, graph) + 1;
+            continue;
+        }
+        if tokens[i + 1] == "=" {
+            i += 3;
+        } else if tokens[i] == "node" || tokens[i] == "edge" {
+            i = attributes(i + 1).1;
+        } else if tokens[i + 1] == "->" {
+            graph.edges.insert((tokens[i].clone(), tokens[i + 2].clone()));
+            i = attributes(i + 3).1;
+        } else {
+            let (node, next) = attributes(i + 1);
+            assert!(graph.nodes.insert(tokens[i].clone(), node).is_none(), "Node {} declared twice", tokens[i]);
+            i = next;
+        }
+        assert_eq!(tokens[i], ";", "Statement not terminated at token {}", i);
+        i += 1;
+    }
+    i
+}
+
+fn parse_dot(text: &str) -> Graph {
+    let tokens = tokenize(text);
+    assert_eq!(&tokens[..3], ["digraph", "Oblique", "{"]);
+    let mut graph = Graph::default();
+    let end = parse_statements(&tokens, 3, &mut graph);
+    assert_eq!(end, tokens.len() - 1);
+    graph
+}
+
+/// Get the text of an HTML-like label made of escaped text, bold and breaks
+fn label_text(label: &str) -> (String, String) {
+    let inner = label.strip_prefix("<<B>").and_then(|l| l.strip_suffix('>')).unwrap();
+    let (bold, rest) = inner.split_once("</B>").unwrap();
+    let rest = rest.strip_prefix("<BR/>").unwrap_or(rest);
+    for text in [bold, rest] {
+        assert!(!text.contains('<') && !text.contains('>'), "Unescaped markup in {}", label);
+    }
+    let unescape = |text: &str| text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&amp;", "&");
+    (unescape(bold), unescape(rest))
+}
+
+fn load(source: &str) -> Database {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("main.oblique");
+    std::fs::write(&path, source).unwrap();
+    let mut db = Database::new();
+    db.import_file(&path).unwrap();
+    db
+}
+
+fn check_round_trip(db: &Database, options: &DotOptions) -> String {
+    let mut output = Vec::new();
+    generate_dot(db, options, &mut output).unwrap();
+    let dot = String::from_utf8(output).unwrap();
+    let graph = parse_dot(&dot);
+
+    // Every object is a node labelled with its rendering and contents
+    assert_eq!(graph.nodes.len(), db.objects.len());
+    for object in db.objects.values() {
+        let ident = object.id.ident.as_deref().unwrap();
+        let node = &graph.nodes[&format!("{}/{}", object.id.type_name, ident)];
+        let (rendered, contents) = label_text(&node["label"]);
+        assert_eq!(rendered, db.render_system.render(&object.id.type_name, ident));
+        match contents.strip_suffix("...") {
+            Some(preview) if contents != object.contents => {
+                assert!(object.contents.starts_with(preview));
+                assert!(preview.chars().count() < object.contents.chars().count());
+            }
+            _ => assert_eq!(contents, object.contents),
+        }
+    }
+
+    // Every link is an edge
+    let edges: BTreeSet<_> = db
+        .objects
+        .values()
+        .flat_map(|o| o.refs.iter().map(move |r| {
+            let target = ObjectId::from(r);
+            (
+                format!("{}/{}", o.id.type_name, o.id.ident.as_deref().unwrap()),
+                format!("{}/{}", target.type_name, target.ident.unwrap()),
+            )
+        }))
+        .collect();
+    assert_eq!(graph.edges, edges);
+
+    check_with_graphviz(&dot);
+    dot
+}
+
+/// Check that Graphviz reads the graph, if it is installed
+fn check_with_graphviz(dot: &str) {
+    let Ok(mut child) = Command::new("dot")
+        .arg("-Tcanon")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+    else {
+        return;
+    };
+    child.stdin.take().unwrap().write_all(dot.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}\n{}", String::from_utf8_lossy(&output.stderr), dot);
+}
+
+#[test]
+fn test_dot_escaping() {
+    let db = load(r#"/type/t Task
+/lazytype/b Bug
+/render t Task "\1"
+/render b <a href="http://bugs/\1">\1</a>
+t/fix-it Copy C:\temp\ to "backup" & <b>check</b> b/12
+t/fix_it Same node name once sanitized, said t/fix-it
+t/emoji x🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀🚀
+"#);
+    let dot = check_round_trip(&db, &DotOptions::default());
+    assert!(dot.contains("<B>Task &quot;emoji&quot;</B><BR/>x🚀"), "{}", dot);
+
+    let options = DotOptions { max_label_len: 3, clustering: Clustering::Type, ..DotOptions::default() };
+    let dot = check_round_trip(&db, &options);
+    assert!(dot.contains("<BR/>x🚀🚀...>"), "{}", dot);
+}
+
+#[test]
+fn test_dot_examples() {
+    for source in [
+        include_str!("../examples/example.oblique"),
+        include_str!("../examples/nuclear_plant.oblique"),
+    ] {
+        let db = load(source);
+        for clustering in [Clustering::None, Clustering::Type, Clustering::Parent] {
+            check_round_trip(&db, &DotOptions { clustering, ..DotOptions::default() });
+        }
+    }
+}
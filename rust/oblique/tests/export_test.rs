@@ -0,0 +1,56 @@
+use std::io;
+use tempfile::tempdir;
+use oblique::{generate_cypher, generate_graphml, generate_mermaid, Clustering, Database, GraphOptions};
+
+fn load(source: &str) -> Database {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("main.oblique");
+    std::fs::write(&path, source).unwrap();
+    let mut db = Database::new();
+    db.import_file(&path).unwrap();
+    db
+}
+
+fn export<F>(db: &Database, options: &GraphOptions, generate: F) -> String
+where
+    F: Fn(&Database, &GraphOptions, &mut Vec<u8>) -> io::Result<()>,
+{
+    let mut output = Vec::new();
+    generate(db, options, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_export_examples() {
+    for source in [
+        include_str!("../examples/example.oblique"),
+        include_str!("../examples/nuclear_plant.oblique"),
+    ] {
+        let db = load(source);
+        let nodes = db.objects.len();
+        let edges: usize = db.objects.values().map(|o| o.refs.len()).sum();
+
+        for clustering in [Clustering::None, Clustering::Type, Clustering::Parent] {
+            let options = GraphOptions { clustering, ..GraphOptions::default() };
+
+            // Every object is a node and every link an edge, in every format
+            let mermaid = export(&db, &options, |db, options, output| generate_mermaid(db, options, output));
+            let lines: Vec<&str> = mermaid.lines().map(str::trim).collect();
+            assert_eq!(lines.iter().filter(|l| l.contains("[\"<b>")).count(), nodes, "{}", mermaid);
+            let arrows = lines.iter().filter(|l| ["-->", "==>", "-.->"].iter().any(|a| l.contains(a))).count();
+            assert_eq!(arrows, edges, "{}", mermaid);
+            assert_eq!(
+                lines.iter().filter(|l| l.starts_with("subgraph ")).count(),
+                lines.iter().filter(|l| **l == "end").count()
+            );
+
+            let graphml = export(&db, &options, |db, options, output| generate_graphml(db, options, output));
+            assert_eq!(graphml.matches("<node id=").count(), nodes, "{}", graphml);
+            assert_eq!(graphml.matches("<edge source=").count(), edges, "{}", graphml);
+
+            let cypher = export(&db, &options, |db, options, output| generate_cypher(db, options, output));
+            assert_eq!(cypher.lines().filter(|l| l.starts_with("CREATE (:Object:")).count(), nodes, "{}", cypher);
+            assert_eq!(cypher.lines().filter(|l| l.starts_with("MATCH ")).count(), edges, "{}", cypher);
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::BTreeMap;
+use oblique::{format_source, parse_string, FormatOptions, ObjectId, Reference};
+
+/// Get the parsed objects of a source, without their locations
+fn objects(source: &str) -> BTreeMap<ObjectId, (String, Vec<Reference>)> {
+    let (_, objects, _) = parse_string(source).unwrap();
+    objects
+        .into_iter()
+        .filter(|o| o.id.ident.is_some())
+        .map(|o| {
+            let mut refs: Vec<_> = o.refs.union(&o.unresolved_refs).cloned().collect();
+            refs.sort();
+            (o.id, (o.contents, refs))
+        })
+        .collect()
+}
+
+#[test]
+fn test_format_examples_preserves_meaning() {
+    for source in [
+        include_str!("../examples/example.oblique"),
+        include_str!("../examples/nuclear_plant.oblique"),
+    ] {
+        for indent_width in [1, 2, 4] {
+            let formatted = format_source(source, &FormatOptions { indent_width });
+            assert_eq!(objects(&formatted), objects(source));
+            assert_eq!(format_source(&formatted, &FormatOptions { indent_width }), formatted);
+        }
+    }
+}
+
+#[test]
+fn test_format_reorders_directive_block() {
+    let source = include_str!("../examples/nuclear_plant.oblique");
+    let formatted = format_source(source, &FormatOptions::default());
+    let directives: Vec<_> = formatted
+        .lines()
+        .take_while(|l| !l.starts_with("p/"))
+        .filter(|l| l.starts_with('/'))
+        .map(|l| l.split([' ', '/']).nth(1).unwrap())
+        .collect();
+    let mut sorted = directives.clone();
+    sorted.sort_by_key(|d| ["type", "lazytype", "ignore", "macro", "render", "style"].iter().position(|k| k == d));
+    assert_eq!(directives, sorted);
+
+    // Comments move with the directives after them
+    assert!(formatted.contains("# Macros for quick entry\n/macro @([a-z0-9]+) u/\\1\n"));
+    assert!(formatted.contains("# Render Users with @ symbol\n/render u @\\1\n"));
+}
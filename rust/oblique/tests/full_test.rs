@@ -25,11 +25,10 @@ o/obj1 Linked to Palpha
     writeln!(main_file, r#"/lazytype/o Object
 /lazytype/u User
 
-/import imported.oblique
+/import --vocab imported.oblique
 
-# Use macro from imported file? No, macros are lexically scoped to file usually, 
-# but my implementation accumulates them in the Parser object. 
-# So macros defined in imported files ARE available after import!
+# Macros are scoped to their file, but a vocabulary import exports the macros
+# and types of the imported file, so they are available after it.
 o/obj2 Linked to Palpha and u/bob
 
 # Test Rendering
@@ -71,3 +70,49 @@ o/obj2 Linked to Palpha and u/bob
     let rendered = db.render_system.render("p", "alpha");
     assert_eq!(rendered, "<a href=\"/project/alpha\">alpha</a>");
 }
+
+#[test]
+fn test_source_locations_across_imports() {
+    let dir = tempdir().unwrap();
+    let main_path = dir.path().join("main.oblique");
+    let imported_path = dir.path().join("imported.oblique");
+
+    std::fs::write(&imported_path, "/lazytype/u User\n\n  u/alice Alice\n").unwrap();
+    std::fs::write(&main_path, "/import --vocab imported.oblique\nitem/1 Ping u/alice and u/bob\n").unwrap();
+
+    let mut db = Database::new();
+    db.import_file(&main_path).unwrap();
+
+    let alice = db.objects.get(&ObjectId { type_name: "u".to_string(), ident: Some("alice".to_string()) }).unwrap();
+    let location = alice.location.as_ref().unwrap();
+    assert_eq!(location.file.as_deref(), Some(imported_path.as_path()));
+    assert_eq!((location.line, location.column), (3, 3));
+    assert_eq!(db.types["u"].location.as_ref().unwrap().file.as_deref(), Some(imported_path.as_path()));
+
+    let item = db.objects.get(&ObjectId { type_name: "item".to_string(), ident: Some("1".to_string()) }).unwrap();
+    assert_eq!(item.location.as_ref().unwrap().file.as_deref(), Some(main_path.as_path()));
+    let columns: Vec<_> = item.occurrences.iter().map(|o| (o.reference.ident.as_str(), o.location.column)).collect();
+    assert_eq!(columns, vec![("alice", 13), ("bob", 25)]);
+
+    // Lazily created objects have no location
+    let bob = db.objects.get(&ObjectId { type_name: "u".to_string(), ident: Some("bob".to_string()) }).unwrap();
+    assert!(bob.location.is_none());
+}
+
+#[test]
+fn test_source_locations_after_macros() {
+    let dir = tempdir().unwrap();
+    let main_path = dir.path().join("main.oblique");
+
+    // The macro lengthens the line before the reference to u/bob
+    std::fs::write(&main_path, "/lazytype/u User\n/macro @(\\w+) u/\\1\n  item/1 Ask @joe about u/bob\n").unwrap();
+
+    let mut db = Database::new();
+    db.import_file(&main_path).unwrap();
+
+    let item = db.objects.get(&ObjectId { type_name: "item".to_string(), ident: Some("1".to_string()) }).unwrap();
+    let location = item.location.as_ref().unwrap();
+    assert_eq!((location.line, location.column), (3, 3));
+    let columns: Vec<_> = item.occurrences.iter().map(|o| (o.reference.ident.as_str(), o.location.column)).collect();
+    assert_eq!(columns, vec![("joe", 3), ("bob", 25)]);
+}
@@ -0,0 +1,74 @@
+use std::fs;
+use tempfile::tempdir;
+use oblique::{generate_html, Database};
+
+#[test]
+fn test_html_site() {
+    let dir = tempdir().unwrap();
+    let main = dir.path().join("main.oblique");
+    fs::write(&main, r#"/type/p Project
+/lazytype/u User
+/render u @\1
+/render p <a href="http://projects/\1">Project \1</a>
+p/zelda The Zelda project
+  t/ Ship it with u/joe
+Review p/zelda & co
+"#).unwrap();
+
+    let mut db = Database::new();
+    db.import_file(&main).unwrap();
+    let site = dir.path().join("site");
+    generate_html(&db, &site).unwrap();
+
+    // An index of the types, and a page per type
+    let index = fs::read_to_string(site.join("index.html")).unwrap();
+    assert!(index.contains("<a href=\"p/index.html\">Project</a>"), "{}", index);
+    let projects = fs::read_to_string(site.join("p/index.html")).unwrap();
+    assert!(projects.contains("The Zelda project"), "{}", projects);
+
+    // References are rendered and linked, and the rest of the text escaped
+    let review = fs::read_to_string(site.join("item/o/_1.html")).unwrap();
+    assert!(review.contains(
+        "Review <a href=\"http://projects/zelda\">Project zelda</a> <a class=\"ref\" href=\"../../p/o/zelda.html\">&#x2197;</a> &amp; co"
+    ), "{}", review);
+
+    // Objects created by reference get a page too
+    let joe = fs::read_to_string(site.join("u/o/joe.html")).unwrap();
+    assert!(joe.contains("Created by reference"), "{}", joe);
+    assert!(joe.contains("<h2>Referenced by</h2>"), "{}", joe);
+
+    // The page of an object lists its children and backlinks, but not its
+    // children as backlinks
+    let zelda = fs::read_to_string(site.join("p/o/zelda.html")).unwrap();
+    let children = zelda.find("<h2>Children</h2>").unwrap();
+    let backlinks = zelda.find("<h2>Referenced by</h2>").unwrap();
+    assert!(zelda[children..backlinks].contains("Ship it with"), "{}", zelda);
+    assert!(zelda[backlinks..].contains("Review"), "{}", zelda);
+    assert!(!zelda[backlinks..].contains("Ship it with"), "{}", zelda);
+
+    // Objects of undeclared types are listed on a page of their type too
+    assert!(index.contains("<a href=\"t/index.html\">t</a>"), "{}", index);
+    let tasks = fs::read_to_string(site.join("t/index.html")).unwrap();
+    assert!(tasks.contains("An undeclared type"), "{}", tasks);
+    assert!(tasks.contains("Ship it with"), "{}", tasks);
+}
+
+#[test]
+fn test_html_site_paths() {
+    let dir = tempdir().unwrap();
+    let main = dir.path().join("main.oblique");
+    fs::write(&main, "/type/p Project\np/index The index project\nSee p/index\n").unwrap();
+
+    let mut db = Database::new();
+    db.import_file(&main).unwrap();
+    let site = dir.path().join("site");
+    generate_html(&db, &site).unwrap();
+
+    // An object named index does not replace the page of its type
+    let projects = fs::read_to_string(site.join("p/index.html")).unwrap();
+    assert!(projects.contains("<h1>Project <code>p</code></h1>"), "{}", projects);
+    assert!(projects.contains("href=\"../p/o/index.html\""), "{}", projects);
+    let index = fs::read_to_string(site.join("p/o/index.html")).unwrap();
+    assert!(index.contains("The index project"), "{}", index);
+    assert!(index.contains("<a href=\"../index.html\">Project</a>"), "{}", index);
+}
@@ -0,0 +1,201 @@
+use std::fs;
+use tempfile::tempdir;
+use oblique::{Database, Error, ImportOptions, ObjectId};
+
+fn id(type_name: &str, ident: &str) -> ObjectId {
+    ObjectId { type_name: type_name.to_string(), ident: Some(ident.to_string()) }
+}
+
+#[test]
+fn test_import_cycle() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("a.oblique"), "/lazytype/u User\n/import b.oblique\nu/a A\n").unwrap();
+    fs::write(dir.path().join("b.oblique"), "u/b B\n/import a.oblique\n").unwrap();
+
+    let mut db = Database::new();
+    let result = db.import_file(dir.path().join("a.oblique"));
+
+    match result {
+        Err(Error::ImportCycle(chain, location)) => {
+            let names: Vec<_> = chain.iter().map(|p| p.file_name().unwrap().to_str().unwrap()).collect();
+            assert_eq!(names, vec!["a.oblique", "b.oblique", "a.oblique"]);
+            assert_eq!(location.line, 2);
+            assert_eq!(location.file.unwrap().file_name().unwrap(), "b.oblique");
+        },
+        _ => panic!("Expected ImportCycle error, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_import_once() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("common.oblique"), "/lazytype/u User\nu/alice Alice\n").unwrap();
+    fs::write(dir.path().join("team.oblique"), "/import --vocab common.oblique\nu/bob Bob\n").unwrap();
+    fs::write(
+        dir.path().join("main.oblique"),
+        "/import common.oblique\n/import team.oblique\n/import ./common.oblique\n",
+    ).unwrap();
+
+    // The diamond-shaped import graph defines u/alice only once
+    let mut db = Database::new();
+    db.import_file(dir.path().join("main.oblique")).unwrap();
+    assert!(db.objects.contains_key(&id("u", "alice")));
+    assert!(db.objects.contains_key(&id("u", "bob")));
+
+    // Including files many times re-parses them, redefining their objects
+    let mut db = Database::new();
+    db.set_import_options(ImportOptions { include_many: true, ..ImportOptions::default() });
+    let result = db.import_file(dir.path().join("main.oblique"));
+    assert!(matches!(result, Err(Error::DuplicateDefinition(_, _, _))));
+}
+
+#[test]
+fn test_scoped_import_lookup() {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("a")).unwrap();
+    fs::create_dir(dir.path().join("b")).unwrap();
+    fs::create_dir(dir.path().join("shared")).unwrap();
+    fs::write(dir.path().join("main.oblique"), "/lazytype/u User\n/import a/x.oblique\n/import b/y.oblique\n").unwrap();
+    fs::write(dir.path().join("a/x.oblique"), "u/x X\n").unwrap();
+    fs::write(dir.path().join("a/common.oblique"), "u/acommon From a\n").unwrap();
+    fs::write(dir.path().join("b/y.oblique"), "/import common.oblique\n").unwrap();
+    fs::write(dir.path().join("shared/common.oblique"), "u/shared From shared\n").unwrap();
+
+    // b/y.oblique does not see its sibling directory's files
+    let mut db = Database::new();
+    let result = db.import_file(dir.path().join("main.oblique"));
+    match result {
+        Err(Error::Import(path, location, inner)) => {
+            assert_eq!(path.to_str(), Some("common.oblique"));
+            assert_eq!(location.file.unwrap(), dir.path().join("b/y.oblique"));
+            let message = inner.to_string();
+            assert!(message.contains(&dir.path().join("b/common.oblique").display().to_string()), "{}", message);
+        },
+        _ => panic!("Expected Import error, got {:?}", result),
+    }
+
+    // Configured include paths are searched after the importing file's directory
+    let mut db = Database::new();
+    db.set_import_options(ImportOptions { include_paths: vec![dir.path().join("shared")], ..ImportOptions::default() });
+    db.import_file(dir.path().join("main.oblique")).unwrap();
+    assert!(db.objects.contains_key(&id("u", "shared")));
+    assert!(!db.objects.contains_key(&id("u", "acommon")));
+}
+
+#[test]
+fn test_oblique_path_environment() {
+    std::env::set_var("OBLIQUE_PATH", std::env::join_paths(["/first", "/second"]).unwrap());
+    let options = ImportOptions::from_env();
+    std::env::remove_var("OBLIQUE_PATH");
+
+    assert_eq!(options.include_paths, vec![std::path::PathBuf::from("/first"), std::path::PathBuf::from("/second")]);
+    assert!(!options.include_many);
+}
+
+#[test]
+fn test_import_inherits_parent() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("main.oblique"), r#"/lazytype/q Quarter
+/type/o Objective
+q/q1
+  /import team-alpha-q1.oblique
+q/q2
+  /import team-alpha-q2.oblique
+/import unrelated.oblique
+"#).unwrap();
+    fs::write(dir.path().join("team-alpha-q1.oblique"), "o/embed Embedding\n  o/search Search\n").unwrap();
+    fs::write(dir.path().join("team-alpha-q2.oblique"), "o/launch Launch\n").unwrap();
+    fs::write(dir.path().join("unrelated.oblique"), "o/misc Misc\n").unwrap();
+
+    let mut db = Database::new();
+    db.import_file(dir.path().join("main.oblique")).unwrap();
+
+    let q1 = oblique::Reference::new("q", "q1", oblique::EdgeKind::Parent);
+    let q2 = oblique::Reference::new("q", "q2", oblique::EdgeKind::Parent);
+    let embed = oblique::Reference::new("o", "embed", oblique::EdgeKind::Parent);
+
+    // Top-level objects of an imported file are children of the enclosing object
+    assert!(db.objects[&id("o", "embed")].refs.contains(&q1));
+    assert!(db.objects[&id("o", "launch")].refs.contains(&q2));
+
+    // Indentation within the imported file still applies
+    let search = &db.objects[&id("o", "search")];
+    assert!(search.refs.contains(&embed));
+    assert!(!search.refs.contains(&q1));
+
+    // Imports at the top level have no parent
+    assert!(db.objects[&id("o", "misc")].refs.is_empty());
+
+    // The tree of all the files
+    assert_eq!(db.top_level_objects(), vec![&id("q", "q1"), &id("q", "q2"), &id("o", "misc")]);
+    assert_eq!(db.children_of(&id("q", "q1")), vec![&id("o", "embed")]);
+    assert_eq!(db.children_of(&id("o", "embed")), vec![&id("o", "search")]);
+    assert_eq!(db.parent_of(&id("o", "search")), Some(id("o", "embed")));
+    assert!(db.children_of(&id("o", "search")).is_empty());
+}
+
+#[test]
+fn test_vocabulary_imports() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("vocab.oblique"), r#"/lazytype/u User
+/macro \b([a-z]+)@ u/\1
+"#).unwrap();
+    fs::write(dir.path().join("data.oblique"), r#"/lazytype/p Project
+/macro \bP([a-z]+) p/\1
+item/data Owned by joe@ for Pzelda
+"#).unwrap();
+    fs::write(dir.path().join("main.oblique"), r#"/import --vocab vocab.oblique
+/import data.oblique
+item/main Owned by ann@ for Pzelda
+"#).unwrap();
+
+    let mut db = Database::new();
+    db.import_file(dir.path().join("main.oblique")).unwrap();
+
+    // The imported data file sees the vocabulary of its importer
+    let data = &db.objects[&id("item", "data")];
+    assert!(data.refs.iter().any(|r| r.type_name == "u" && r.ident == "joe"));
+    assert!(data.refs.iter().any(|r| r.type_name == "p" && r.ident == "zelda"));
+
+    // The macros of the data file stay local to it
+    let main = &db.objects[&id("item", "main")];
+    assert!(main.refs.iter().any(|r| r.type_name == "u" && r.ident == "ann"));
+    assert_eq!(main.contents, "Owned by u/ann for Pzelda");
+
+    // Its types too: referring to them is an error
+    fs::write(dir.path().join("main.oblique"), "/import data.oblique\nitem/main See p/zelda\n").unwrap();
+    let mut db = Database::new();
+    let result = db.import_file(dir.path().join("main.oblique"));
+    match result {
+        Err(Error::TypeNotInScope(type_name, location)) => {
+            assert_eq!(type_name, "p");
+            assert_eq!((location.line, location.column), (2, 15));
+        },
+        _ => panic!("Expected TypeNotInScope error, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_vocabulary_of_file_imported_twice() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("vocab.oblique"), "/lazytype/u User\n/macro \\b([a-z]+)@ u/\\1\n").unwrap();
+    fs::write(dir.path().join("team.oblique"), "/import --vocab vocab.oblique\nitem/team With joe@\n").unwrap();
+    fs::write(dir.path().join("main.oblique"), "/import team.oblique\n/import --vocab vocab.oblique\nitem/main With ann@\n").unwrap();
+
+    // The second import of the vocabulary is skipped, but still exports it
+    let mut db = Database::new();
+    db.import_file(dir.path().join("main.oblique")).unwrap();
+    assert!(db.objects[&id("item", "team")].refs.iter().any(|r| r.ident == "joe"));
+    assert!(db.objects[&id("item", "main")].refs.iter().any(|r| r.ident == "ann"));
+}
+
+#[test]
+fn test_vocabulary_imported_twice_applies_macros_once() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("vocab.oblique"), "/macro \\bhi\\b hi there\n").unwrap();
+    fs::write(dir.path().join("main.oblique"), "/import --vocab vocab.oblique\n/import --vocab vocab.oblique\nitem/main hi\n").unwrap();
+
+    let mut db = Database::new();
+    db.import_file(dir.path().join("main.oblique")).unwrap();
+    assert_eq!(db.objects[&id("item", "main")].contents, "hi there");
+}
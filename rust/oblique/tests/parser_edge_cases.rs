@@ -75,3 +75,28 @@ fn test_empty_input() {
     assert_eq!(types.len(), 1); // Default item type
     assert!(objects.is_empty());
 }
+
+#[test]
+fn test_verbatim_contents() {
+    let input = "/type/p Project\n/lazytype/u User\np/zelda Ship  Component/Project (lead u/joe).  # Owner\n";
+
+    let (_, objects, _) = parse_string(input).unwrap();
+    let obj = objects.iter().find(|o| o.id.ident.as_deref() == Some("zelda")).unwrap();
+    assert_eq!(obj.contents, "Ship  Component/Project (lead u/joe).");
+    let refs: Vec<_> = obj.occurrences.iter().map(|o| &o.reference).map(|r| format!("{}/{}", r.type_name, r.ident)).collect();
+    assert_eq!(refs, vec!["u/joe"]);
+}
+
+#[test]
+fn test_style_declarations() {
+    let (_, _, render_system) = parse_string("/style b shape=diamond label=\"A bug\"\n/style b shape=box\n").unwrap();
+    let style = render_system.style("b").unwrap();
+    assert_eq!(style, &vec![
+        ("label".to_string(), "A bug".to_string()),
+        ("shape".to_string(), "box".to_string()),
+    ]);
+
+    assert!(parse_string("/style b\n").is_err());
+    assert!(parse_string("/style b shape\n").is_err());
+    assert!(parse_string("/style b label=\"open\n").is_err());
+}
@@ -0,0 +1,91 @@
+use std::collections::BTreeSet;
+use std::fs;
+use tempfile::tempdir;
+use oblique::{proto, Database, ObjectId, Reference};
+
+/// The objects of a database with their contents and their links, with the
+/// count of each
+type Summary = BTreeSet<(ObjectId, String, Vec<(Reference, usize)>)>;
+
+fn summary(db: &Database) -> Summary {
+    db.objects
+        .values()
+        .map(|o| {
+            let mut refs: Vec<_> = o.refs.iter().map(|r| (r.clone(), r.count)).collect();
+            refs.sort();
+            (o.id.clone(), o.contents.clone(), refs)
+        })
+        .collect()
+}
+
+#[test]
+fn test_proto_examples() {
+    let dir = tempdir().unwrap();
+    for source in [
+        include_str!("../examples/example.oblique"),
+        include_str!("../examples/nuclear_plant.oblique"),
+    ] {
+        let path = dir.path().join("main.oblique");
+        fs::write(&path, source).unwrap();
+        let mut db = Database::new();
+        db.import_file(&path).unwrap();
+        // Both formats load the same database back, with its hierarchy
+        let binary = dir.path().join("db.pb");
+        fs::write(&binary, proto::encode(&db)).unwrap();
+        let text = dir.path().join("db.textproto");
+        fs::write(&text, proto::to_text(&db)).unwrap();
+        for path in [binary, text] {
+            let loaded = Database::load_proto(&path).unwrap();
+            assert_eq!(summary(&loaded), summary(&db), "{}", path.display());
+            assert_eq!(loaded.top_level_objects(), db.top_level_objects());
+            assert_eq!(proto::to_message(&loaded), proto::to_message(&db));
+        }
+    }
+}
+
+#[test]
+fn test_load_cpp_output() {
+    // The expected output of a test of the C++ resolution, without lines
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("db.txtpb");
+    fs::write(&path, r#"
+        type {type: "item" contents: "Item type" flavor: LAZY}
+        type {type: "task" contents: "Task"}
+        object {id {type: "task" ident: "conquer"}
+                contents: "Conquer it"}
+        object {id {type: "item" ident: "1"}
+                contents: "All the world. task/conquer"
+                refs {type: "task" ident: "conquer"}}
+        object {id {type: "item"} contents: "No identifier"}
+        error {filename: "main.oblique" lineno: 3 error_message: "Invalid reference to strict type 'task/other'"}
+    "#).unwrap();
+
+    let db = Database::load_proto(&path).unwrap();
+    let conquer = ObjectId { type_name: "task".to_string(), ident: Some("conquer".to_string()) };
+    let item = ObjectId { type_name: "item".to_string(), ident: Some("1".to_string()) };
+    assert_eq!(db.referrers(&conquer).collect::<Vec<_>>(), [&item]);
+    assert_eq!(db.objects[&item].refs.len(), 1);
+
+    // Objects without an identifier get one, as when parsed
+    let generated = ObjectId { type_name: "item".to_string(), ident: Some("_1".to_string()) };
+    assert_eq!(db.objects[&generated].contents, "No identifier");
+
+    assert_eq!(db.diagnostics.len(), 1);
+    assert_eq!(db.diagnostics[0].location.as_ref().unwrap().line, 3);
+
+    fs::write(&path, "object {id {type: \"item\"} contents: \"unterminated}\n").unwrap();
+    let err = Database::load_proto(&path).unwrap_err();
+    assert!(err.to_string().starts_with("Invalid protobuf: "), "{}", err);
+}
+
+#[test]
+fn test_schema_matches_cpp_parser() {
+    // The crate builds from a copy of the schema of the C++ parser, which is
+    // only there in a checkout of the whole repository
+    let manifest_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+    let Ok(original) = fs::read_to_string(manifest_dir.join("../../oblique/data.proto")) else {
+        return;
+    };
+    let copy = fs::read_to_string(manifest_dir.join("proto/data.proto")).unwrap();
+    assert!(copy == original, "proto/data.proto differs from oblique/data.proto; copy it again");
+}
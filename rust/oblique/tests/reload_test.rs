@@ -0,0 +1,85 @@
+use std::fs;
+use tempfile::tempdir;
+use oblique::{Database, ObjectId};
+
+fn id(type_name: &str, ident: &str) -> ObjectId {
+    ObjectId { type_name: type_name.to_string(), ident: Some(ident.to_string()) }
+}
+
+#[test]
+fn test_reload_changed_file() {
+    let dir = tempdir().unwrap();
+    let main = dir.path().join("main.oblique");
+    let tasks = dir.path().join("tasks.oblique");
+    fs::write(&main, "/lazytype/u User\n/type/t Task\n/import tasks.oblique\nitem/main See t/a\n").unwrap();
+    fs::write(&tasks, "t/a Task A for u/joe\nt/b Task B\n").unwrap();
+
+    let mut db = Database::new();
+    db.import_file(&main).unwrap();
+    assert_eq!(db.source_files(), vec![main.as_path(), tasks.as_path()]);
+    assert!(db.objects.contains_key(&id("u", "joe")));
+
+    // Only the changed file is parsed again
+    fs::write(&main, "/lazytype/u User\n/type/t Task\nitem/main Not parsed again\n").unwrap();
+    fs::write(&tasks, "t/a Task A for u/ann\n").unwrap();
+    db.reload_file(&tasks).unwrap();
+
+    assert_eq!(db.objects[&id("item", "main")].contents, "See t/a");
+    assert_eq!(db.objects[&id("t", "a")].contents, "Task A for u/ann");
+    assert!(!db.objects.contains_key(&id("t", "b")));
+
+    // Objects created for references that are gone are dropped
+    assert!(db.objects.contains_key(&id("u", "ann")));
+    assert!(!db.objects.contains_key(&id("u", "joe")));
+    assert!(db.referrers(&id("t", "a")).any(|r| *r == id("item", "main")));
+}
+
+#[test]
+fn test_reload_diagnostics() {
+    let dir = tempdir().unwrap();
+    let main = dir.path().join("main.oblique");
+    let tasks = dir.path().join("tasks.oblique");
+    fs::write(&main, "/type/t Task\n/import tasks.oblique\nitem/main See t/a and t/missing\n").unwrap();
+    fs::write(&tasks, "t/b Task B\nt/b Again\n").unwrap();
+
+    let mut db = Database::new();
+    db.set_collect_diagnostics(true);
+    db.import_file(&main).unwrap();
+    assert_eq!(db.diagnostics.len(), 3);
+
+    // Fixed errors disappear, and the others are not reported twice
+    fs::write(&tasks, "t/a Task A\n").unwrap();
+    db.reload_file(&tasks).unwrap();
+    let messages: Vec<_> = db.diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(messages.len(), 1, "{:?}", messages);
+    assert!(messages[0].contains("t/missing"), "{:?}", messages);
+}
+
+#[test]
+fn test_reload_changed_imports() {
+    let dir = tempdir().unwrap();
+    let main = dir.path().join("main.oblique");
+    let tasks = dir.path().join("tasks.oblique");
+    let more = dir.path().join("more.oblique");
+    fs::write(&main, "/type/t Task\n/import tasks.oblique\n").unwrap();
+    fs::write(&tasks, "t/a Task A\n").unwrap();
+    fs::write(&more, "t/b Task B\n").unwrap();
+
+    let mut db = Database::new();
+    db.import_file(&main).unwrap();
+
+    // A new import is parsed, along with everything else
+    fs::write(&tasks, "t/a Task A\n/import more.oblique\n").unwrap();
+    db.reload_file(&tasks).unwrap();
+    assert!(db.objects.contains_key(&id("t", "b")));
+    assert_eq!(db.source_files().len(), 3);
+
+    // Automatic identifiers stay numbered in definition order
+    fs::write(&main, "/type/t Task\nt/ First\n/import tasks.oblique\nt/ Last\n").unwrap();
+    db.reload_file(&main).unwrap();
+    fs::write(&tasks, "t/a Task A, changed\n/import more.oblique\n").unwrap();
+    db.reload_file(&tasks).unwrap();
+    assert_eq!(db.objects[&id("t", "_1")].contents, "First");
+    assert_eq!(db.objects[&id("t", "_2")].contents, "Last");
+    assert_eq!(db.objects[&id("t", "a")].contents, "Task A, changed");
+}
@@ -0,0 +1,95 @@
+use std::fs;
+use tempfile::tempdir;
+use oblique::{rename, write_auto_ids, AutoIdStrategy, Database, EdgeKind, Error, ObjectId, Reference, RenameTarget};
+
+fn reference(type_name: &str, ident: &str) -> Reference {
+    Reference::new(type_name, ident, EdgeKind::Mention)
+}
+
+#[test]
+fn test_rename_object_across_imports() {
+    let dir = tempdir().unwrap();
+    let main = dir.path().join("main.oblique");
+    let tasks = dir.path().join("tasks.oblique");
+    fs::write(&main, "/type/p Project\n/type/t Task\n/macro zelda! p/zelda\np/zelda The  Zelda project\n/import tasks.oblique\n").unwrap();
+    fs::write(&tasks, "t/a Ship (p/zelda).\nt/b Ship zelda! again\n").unwrap();
+
+    let mut db = Database::new();
+    db.import_file(&main).unwrap();
+    let target = RenameTarget::Object(reference("p", "zelda"), "hyrule".to_string());
+    let rename = rename(&db, &target).unwrap();
+
+    // The macro replacement is rewritten, but the reference it produced is not
+    // in the text of its line
+    assert_eq!(rename.edits.len(), 2);
+    assert_eq!(rename.warnings.len(), 1);
+    assert_eq!(rename.warnings[0].location.as_ref().unwrap().line, 2);
+
+    rename.apply().unwrap();
+    assert_eq!(
+        fs::read_to_string(&main).unwrap(),
+        "/type/p Project\n/type/t Task\n/macro zelda! p/hyrule\np/hyrule The  Zelda project\n/import tasks.oblique\n"
+    );
+    assert_eq!(fs::read_to_string(&tasks).unwrap(), "t/a Ship (p/hyrule).\nt/b Ship zelda! again\n");
+
+    db.reload().unwrap();
+    let hyrule = ObjectId::from(&reference("p", "hyrule"));
+    assert_eq!(db.referrers(&hyrule).count(), 2);
+    assert!(!db.objects.contains_key(&ObjectId::from(&reference("p", "zelda"))));
+}
+
+#[test]
+fn test_rename_type() {
+    let dir = tempdir().unwrap();
+    let main = dir.path().join("main.oblique");
+    fs::write(&main, "/type/t Task\n/render t #\\1\nt/a First\nt/ Second, after t/a\n").unwrap();
+
+    let mut db = Database::new();
+    db.import_file(&main).unwrap();
+    let rename = rename(&db, &RenameTarget::Type("t".to_string(), "tsk".to_string())).unwrap();
+    assert!(rename.warnings.is_empty());
+    rename.apply().unwrap();
+    assert_eq!(
+        fs::read_to_string(&main).unwrap(),
+        "/type/tsk Task\n/render tsk #\\1\ntsk/a First\ntsk/ Second, after tsk/a\n"
+    );
+}
+
+#[test]
+fn test_rename_errors() {
+    let dir = tempdir().unwrap();
+    let main = dir.path().join("main.oblique");
+    fs::write(&main, "/type/t Task\nt/a First\nt/b Second\n").unwrap();
+
+    let mut db = Database::new();
+    db.import_file(&main).unwrap();
+    let object = |from: &str, to: &str| RenameTarget::Object(reference("t", from), to.to_string());
+    let typ = |from: &str, to: &str| RenameTarget::Type(from.to_string(), to.to_string());
+    for target in [object("a", "b"), object("c", "d"), object("a", "no spaces"), typ("t", "item"), typ("item", "i"), typ("t", "T")] {
+        assert!(matches!(rename(&db, &target), Err(Error::Rename(_))), "{:?}", target);
+    }
+}
+
+#[test]
+fn test_write_auto_ids() {
+    let dir = tempdir().unwrap();
+    let main = dir.path().join("main.oblique");
+    fs::write(&main, "/type/t Task\nt/ First\nA plain item\nt/\tSecond  # note\n").unwrap();
+
+    let mut db = Database::new();
+    db.set_auto_id_strategy(AutoIdStrategy::ContentHash);
+    db.import_file(&main).unwrap();
+    let idents: Vec<_> = db.objects.keys().filter(|id| id.type_name == "t").cloned().collect();
+    write_auto_ids(&db).unwrap().apply().unwrap();
+
+    // The identifiers are now explicit, and the same
+    let text = fs::read_to_string(&main).unwrap();
+    assert!(text.contains("\nA plain item\n"), "{}", text);
+    db.reload().unwrap();
+    for id in &idents {
+        let ident = id.ident.as_deref().unwrap();
+        assert!(text.contains(&format!("t/{} ", ident)) || text.contains(&format!("t/{}\t", ident)), "{}", text);
+        assert!(db.objects.contains_key(id));
+    }
+    assert!(write_auto_ids(&db).unwrap().edits.is_empty());
+}